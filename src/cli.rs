@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use crate::game::tootris::GameMode;
+use crate::settings::OPTION_BOT_MAX_PIECES;
+use crate::ui::config::{default_path, UserConfig};
+
+pub static HELP: &str = "\
+tootris, falling blocks in the terminal
+
+usage:
+  tootris [play] [options]    play a game (the default)
+  tootris replay [FILE]       watch a recorded game, the last finished one if no file is given
+  tootris scores [--mode M]   print the high score tables
+  tootris bot [options]       let the computer play and print how it did
+  tootris help                show this text
+
+options:
+  --mode M         marathon, sprint or ultra
+  --seed N         deal the same pieces in every game
+  --width N        width of the level, border included
+  --height N       height of the level, floor included
+  --config FILE    read the settings from FILE instead of the default config file
+  --set S=V        override a setting of the config file, e.g. --set display.theme=ascii
+  --text           play with plain text lines on stdin and stdout (play only)
+  --games N        number of games the bot plays (bot only)
+  --pieces N       pieces per bot game at most (bot only)
+";
+
+/**
+* What to do, picked by the first argument
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    Play { text: bool },
+    Replay { file: Option<PathBuf> },
+    Scores { mode: Option<GameMode> },
+    Bot { games: usize, pieces: usize },
+    Help,
+}
+
+/**
+* The parsed command line. Everything that changes a setting ends up in overrides, which are
+* checked along with the config file.
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cli {
+    pub command: Command,
+    pub config_path: Option<PathBuf>,
+    /// section.option=value settings, applied over the config file in order
+    pub overrides: Vec<String>,
+}
+
+impl Cli {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (name, rest) = match args.first().map(String::as_str) {
+            Some(arg) if !arg.starts_with('-') => (arg, &args[1..]),
+            _ => ("play", args),
+        };
+        let mut command = match name {
+            "play" => Command::Play { text: false },
+            "replay" => Command::Replay { file: None },
+            "scores" => Command::Scores { mode: None },
+            "bot" => Command::Bot { games: 1, pieces: OPTION_BOT_MAX_PIECES },
+            "help" => Command::Help,
+            _ => return Err(format!("unknown command '{}', see tootris help", name)),
+        };
+        let mut cli = Cli { command: Command::Help, config_path: None, overrides: Vec::new() };
+        let mut rest = rest.iter();
+        while let Some(arg) = rest.next() {
+            if arg == "-h" || arg == "--help" {
+                command = Command::Help;
+                break;
+            }
+            //both --width 12 and --width=12 work
+            let (option, inline_value) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[i + 1..].to_string())),
+                _ => (arg.as_str(), None),
+            };
+            if !option.starts_with("--") {
+                match &mut command {
+                    Command::Replay { file } if file.is_none() => *file = Some(PathBuf::from(arg)),
+                    _ => return Err(format!("unexpected argument '{}', see tootris help", arg)),
+                }
+                continue;
+            }
+            if option == "--text" {
+                match &mut command {
+                    Command::Play { text } => *text = true,
+                    _ => return Err(format!("--text only goes with play, not {}", name)),
+                }
+                continue;
+            }
+            let mut value = || inline_value.clone().or_else(|| rest.next().cloned())
+                .ok_or_else(|| format!("{} needs a value", option));
+            match (option, &mut command) {
+                ("--config", _) => cli.config_path = Some(PathBuf::from(value()?)),
+                ("--set", _) => cli.overrides.push(value()?),
+                ("--mode", Command::Scores { mode }) => {
+                    let key = value()?;
+                    *mode = Some(GameMode::from_key(&key)
+                        .ok_or_else(|| format!("unknown mode '{}', use marathon, sprint or ultra", key))?);
+                }
+                ("--mode", Command::Play { .. }) | ("--mode", Command::Bot { .. }) => {
+                    cli.overrides.push(format!("game.mode={}", value()?));
+                }
+                ("--seed", Command::Play { .. }) | ("--seed", Command::Bot { .. }) => {
+                    cli.overrides.push(format!("game.seed={}", value()?));
+                }
+                ("--width", Command::Play { .. }) | ("--width", Command::Bot { .. }) => {
+                    cli.overrides.push(format!("game.width={}", value()?));
+                }
+                ("--height", Command::Play { .. }) | ("--height", Command::Bot { .. }) => {
+                    cli.overrides.push(format!("game.height={}", value()?));
+                }
+                ("--games", Command::Bot { games, .. }) => *games = count_option(option, &value()?, 1000)?,
+                ("--pieces", Command::Bot { pieces, .. }) => *pieces = count_option(option, &value()?, 100_000)?,
+                _ => return Err(format!("unknown option {} for {}, see tootris help", option, name)),
+            }
+        }
+        cli.command = command;
+        Ok(cli)
+    }
+
+    /// The config file with the overrides applied, and the problems found in the file.
+    /// A bad override is an error, so is a config file that was asked for but isn't there.
+    pub fn config(&self) -> Result<(UserConfig, Vec<String>), String> {
+        if let Some(path) = self.config_path.as_ref() {
+            if !path.is_file() {
+                return Err(format!("no config file at {}", path.display()));
+            }
+        }
+        let (mut config, warnings) = UserConfig::load(self.config_path.clone().or_else(default_path));
+        for setting in self.overrides.iter() {
+            config.set(setting)?;
+        }
+        Ok((config, warnings))
+    }
+}
+
+fn count_option(option: &str, value: &str, max: usize) -> Result<usize, String> {
+    value.parse::<usize>().ok()
+        .filter(|n| *n >= 1 && *n <= max)
+        .ok_or_else(|| format!("{} must be a number from 1 to {}, not '{}'", option, max, value))
+}
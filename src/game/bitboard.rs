@@ -0,0 +1,104 @@
+use crate::game::tootris::{GameBlock, GameMatrix, Point};
+
+/**
+* Which cells of the level are taken, one bit per cell and one integer per row, bit x for
+* column x. The level keeps the colors, this is kept next to it so collisions, full rows and
+* clearing them take a few integer operations per row instead of comparing blocks cell by cell.
+* Anything that isn't empty is taken, the walls and the floor included.
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct Occupancy {
+    rows: Vec<u64>,
+    width: usize,
+}
+
+impl Occupancy {
+    /// Levels are never wider than this
+    pub const MAX_WIDTH: usize = u64::BITS as usize;
+
+    pub fn from_level(level: &GameMatrix) -> Self {
+        let width = level.first().map_or(0, |row| row.len());
+        assert!(width <= Self::MAX_WIDTH, "a level of {} columns doesn't fit in a bitboard", width);
+        let rows = level.iter().map(|row| {
+            row.iter().enumerate()
+                .filter(|(_, block)| **block != GameBlock::Empty)
+                .fold(0u64, |bits, (x, _)| bits | 1 << x)
+        }).collect();
+        Occupancy { rows, width }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The bits of the row, bit x for column x
+    pub fn row(&self, y: usize) -> u64 {
+        self.rows[y]
+    }
+
+    /// Outside the level counts as taken
+    pub fn is_taken(&self, point: &Point) -> bool {
+        point.x >= self.width || point.y >= self.rows.len() || self.rows[point.y] & 1 << point.x != 0
+    }
+
+    /// True if any of the points is taken, or outside the level
+    pub fn collides(&self, points: &[Point]) -> bool {
+        points.iter().any(|point| self.is_taken(point))
+    }
+
+    /// Takes the cells, a piece locking in place
+    pub fn fill(&mut self, points: &[Point]) {
+        for point in points {
+            if point.x < self.width && point.y < self.rows.len() {
+                self.rows[point.y] |= 1 << point.x;
+            }
+        }
+    }
+
+    /// The rows with every cell taken from wall to wall, top first. The floor isn't one of them.
+    pub fn full_rows(&self) -> Vec<usize> {
+        let full = self.full_row();
+        let floor = self.rows.len().saturating_sub(1);
+        (0..floor).filter(|y| self.rows[*y] == full).collect()
+    }
+
+    /**
+    * Takes the rows out and lets the ones above fall in their place, empty rows between the walls
+    * come in at the top. The rows are expected top first, as full_rows gives them.
+    */
+    pub fn collapse(&mut self, rows: &[usize]) {
+        let empty = self.empty_row();
+        //taking a row out and putting one in at the top leaves the rows below where they were
+        for y in rows {
+            self.rows.remove(*y);
+            self.rows.insert(0, empty);
+        }
+    }
+
+    /// Empty cells that have a taken one somewhere above them in the same column
+    pub fn holes(&self) -> usize {
+        let mut covered = 0u64;
+        let mut holes = 0;
+        for row in self.rows.iter() {
+            holes += (covered & !row).count_ones() as usize;
+            covered |= row;
+        }
+        holes
+    }
+
+    fn full_row(&self) -> u64 {
+        if self.width == Self::MAX_WIDTH { u64::MAX } else { (1 << self.width) - 1 }
+    }
+
+    /// A row with only the walls
+    fn empty_row(&self) -> u64 {
+        match self.width {
+            0 => 0,
+            width => 1 | 1 << (width - 1),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::game::bitboard::Occupancy;
+use crate::game::game_loop_controller::EvilGameMaster;
+use crate::game::piece::Piece;
+use crate::game::tootris::{GameMatrix, GameResult, GameState, PlayerMove, Point, Rotation};
+
+/// Weights of the placement score, the usual ones for this kind of bot
+static WEIGHT_HEIGHT: f64 = -0.51;
+static WEIGHT_LINES: f64 = 0.76;
+static WEIGHT_HOLES: f64 = -0.36;
+static WEIGHT_BUMPINESS: f64 = -0.18;
+
+static ORIENTATIONS: [(PlayerMove, Rotation); 4] = [
+    (PlayerMove::OrientUp, Rotation::OrientUp),
+    (PlayerMove::OrientRight, Rotation::OrientRight),
+    (PlayerMove::OrientDown, Rotation::OrientDown),
+    (PlayerMove::OrientLeft, Rotation::OrientLeft),
+];
+
+/**
+* The moves that put the piece in the best spot the bot can find: every orientation and every
+* column the piece can be shifted to is dropped and scored on the resulting stack.
+* Ends with a hard drop.
+*/
+pub fn plan(level: &GameMatrix, piece: &Piece) -> Vec<PlayerMove> {
+    let board = Occupancy::from_level(level);
+    let mut best: Option<(f64, Vec<PlayerMove>)> = None;
+    for (orient, rotation) in ORIENTATIONS.iter() {
+        let mut turned = piece.clone();
+        turned.rotate(rotation);
+        if is_colliding(&board, &turned, &turned.location) {
+            continue;
+        }
+        for step in [PlayerMove::StepLeft, PlayerMove::StepRight].iter() {
+            let mut location = turned.location;
+            let mut moves = vec![*orient];
+            loop {
+                let score = placement_score(&board, &turned, location);
+                if best.as_ref().is_none_or(|(best_score, _)| score > *best_score) {
+                    let mut planned = moves.clone();
+                    planned.push(PlayerMove::HardDrop);
+                    best = Some((score, planned));
+                }
+                let next = match step {
+                    PlayerMove::StepLeft if location.x > 0 => Point { x: location.x - 1, y: location.y },
+                    PlayerMove::StepRight => Point { x: location.x + 1, y: location.y },
+                    _ => break,
+                };
+                if is_colliding(&board, &turned, &next) {
+                    break;
+                }
+                location = next;
+                moves.push(*step);
+            }
+        }
+    }
+    best.map_or(vec![PlayerMove::HardDrop], |(_, moves)| moves)
+}
+
+/**
+* Plays a whole game on the master, as fast as it goes. The game is stepped one frame at a time
+* without waiting for the clock, so the times in the result are game time, not how long the
+* bot took. Stops after max_pieces if the game hasn't ended by then.
+*/
+pub fn play(master: &mut EvilGameMaster, max_pieces: usize) -> GameResult {
+    master.countdown_ms = 0;
+    master.new_game();
+    master.resume_game();
+    let mut placed = 0;
+    while master.state != GameState::End && placed < max_pieces {
+        master.step();
+        if master.state != GameState::Playing || master.active_piece.is_none() {
+            continue;
+        }
+        let piece = master.active_piece.clone().unwrap();
+        for player_move in plan(&master.level, &piece) {
+            master.process_move(&player_move);
+        }
+        placed += 1;
+    }
+    master.result()
+}
+
+fn is_colliding(board: &Occupancy, piece: &Piece, location: &Point) -> bool {
+    piece.sticks_out(Some(location)) || board.collides(&piece.points(Some(location)))
+}
+
+/// How good the stack looks with the piece dropped straight down from the location
+fn placement_score(board: &Occupancy, piece: &Piece, location: Point) -> f64 {
+    let mut landed = location;
+    while !is_colliding(board, piece, &Point { x: landed.x, y: landed.y + 1 }) {
+        landed.y += 1;
+    }
+    let mut after = board.clone();
+    after.fill(&piece.points(Some(&landed)));
+
+    //the cleared rows go, everything above them moves down
+    let full_rows = after.full_rows();
+    after.collapse(&full_rows);
+
+    let heights = column_heights(&after);
+    let bumpiness: usize = heights.windows(2)
+        .map(|pair| (pair[0] as i64 - pair[1] as i64).unsigned_abs() as usize)
+        .sum();
+    WEIGHT_HEIGHT * heights.iter().sum::<usize>() as f64
+        + WEIGHT_LINES * full_rows.len() as f64
+        + WEIGHT_HOLES * after.holes() as f64
+        + WEIGHT_BUMPINESS * bumpiness as f64
+}
+
+/// Height of the stack in each column inside the walls
+fn column_heights(board: &Occupancy) -> Vec<usize> {
+    let rows = board.height() - 1;
+    (1..board.width() - 1).map(|x| {
+        (0..rows).find(|y| board.row(*y) & 1 << x != 0)
+            .map_or(0, |y| rows - y)
+    }).collect()
+}
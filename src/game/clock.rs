@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::settings::OPTION_FRAME_RATE;
+
+/**
+* Where the game gets its time from. The game itself counts frames (gravity, lock delay, line
+* clears, the countdown, mode time limits, replays), the clock only says how many frames are
+* due by now, so swapping it for a ManualClock makes all of it run on command.
+*/
+pub trait Clock: Send + Sync {
+    /// Milliseconds since some fixed point, never going backwards
+    fn now_ms(&self) -> u64;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/**
+* The monotonic system clock, counting from when it was made
+*/
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        RealClock { start: Instant::now() }
+    }
+
+    pub fn shared() -> SharedClock {
+        Arc::new(Self::new())
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+/**
+* A clock that only moves when told to. Clones share the same time, so a test can keep one
+* and hand another to the game.
+*/
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now.fetch_add(ms, Ordering::SeqCst);
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// Whole frames in the time, rounded to the nearest
+pub fn ms_to_frames(ms: u64) -> u64 {
+    (ms * OPTION_FRAME_RATE + 500) / 1000
+}
+
+pub fn frames_to_ms(frames: u64) -> u64 {
+    frames * 1000 / OPTION_FRAME_RATE
+}
@@ -1,32 +1,96 @@
-extern crate stopwatch;
 
-use rand::{Rng, thread_rng};
-use stopwatch::Stopwatch;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 
+use crate::game::clock::{frames_to_ms, ms_to_frames, RealClock, SharedClock};
 use crate::game::piece::{Piece};
-use crate::game::piece_types::{PieceDefinition, PieceDefinitions, PieceFreezeProperty};
+use crate::game::piece_types::{PieceDefinitions, PLAYABLE_PIECES};
+use crate::game::replay::Replay;
+use crate::game::stats::GameStats;
+use crate::game::bitboard::Occupancy;
 
-use crate::game::tootris::{BlockColor, Communique, Controller, GameBlock, GameBroadcaster, GameMatrix, GameState, GameUpdateReceiver, Master2RenderCommunique, Master2UICommunique, PlayerMove, Point, Rotation, UI2MasterCommunique, UiCommand, ControllerCommand};
-use crate::game::tootris::Communique::Update;
+use crate::game::tootris::{BlockColor, GameBlock, GameMatrix, GameState, PlayerMove, Point, Rotation,
+                           UiCommand, GameMode, GameResult, HudInfo, Randomizer, Countdown, GameConfig};
+use crate::game::protocol::{apply_changes, CellUpdate, Controller, GameBroadcaster, GameEvent, GameUpdateReceiver,
+                            UiEvent};
 use crate::settings::*;
 use crate::game::tootris::GameState::{Tootris, Exit};
 use std::ops::Mul;
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub struct EvilGameMaster {
     pub level: GameMatrix,
+    /// Which cells of the level are taken, kept in step with it
+    pub occupancy: Occupancy,
     pub gom: Option<GameMatrix>,
     pub completed_rows: Vec<usize>,
     pub active_piece: Option<Piece>,
     speed: usize,
     pub score: usize,
-    pub num_pieces: usize,
+    pub lines: usize,
+    pub stats: GameStats,
+    pub mode: GameMode,
+    pub randomizer: Randomizer,
+    pub config: GameConfig,
+    /// Length of the countdown in ms, 0 starts play right away
+    pub countdown_ms: u64,
+    /// Gravity multiplier while soft drop is held, 0 for sonic drop
+    pub soft_drop_factor: usize,
+    soft_drop: bool,
+    /// Frames a piece can rest on the stack before it locks
+    pub lock_delay_frames: u64,
+    /// Frames between a piece locking and the next one showing up
+    pub are_frames: u64,
+    /// Frames completed rows stay before they are cleared
+    pub line_clear_frames: u64,
+    /// Include the ghost piece in level updates
+    pub ghost: bool,
+    /// Number of upcoming pieces sent to the ui
+    pub preview: usize,
     piece_map: PieceDefinitions,
     piece_bucket: Vec<Piece>,
-    pub sw: Stopwatch,
+    rng: StdRng,
+    clock: SharedClock,
+    /// Clock time the frames are counted from
+    clock_start_ms: u64,
+    /// Frames run by the clock so far
+    frame: u64,
+    /// Frames actually spent playing, excluding pauses
+    pub game_frames: u64,
+    countdown_frames: u64,
+    /// Frames since the active piece last fell a row
+    fall_frames: u64,
+    /// Frames the active piece has been resting on the stack
+    lock_frames: u64,
+    lock_resets: usize,
+    /// Frames left before the next piece shows up
+    entry_frames: u64,
+    clear_frames: u64,
+    hud_frames: u64,
+    /// Moves that came in while rows were cleared or before the next piece showed up, for that piece,
+    /// and an exit that came in after them
+    held_events: Vec<UiEvent>,
+    /// Recording of the current (or last) game
+    pub replay: Replay,
+    /// Where the recording of every finished game is written, if anywhere
+    pub replay_file: Option<PathBuf>,
+    replay_frames: u64,
+    replay_frame: usize,
     pub state: GameState,
-    pub render_slave: Option<GameBroadcaster<Master2RenderCommunique>>,
-    pub ui_slave: Option<GameBroadcaster<Master2UICommunique>>,
-    pub ui_listener: Option<GameUpdateReceiver<UI2MasterCommunique>>,
+    pub render_slave: Option<GameBroadcaster<GameEvent>>,
+    /// The renderer has the level as of the last update, the next ones only carry the cells that changed
+    board_sent: bool,
+    /// Cells of the level changed by locks and clears since the last update
+    dirty_cells: Vec<Point>,
+    /// The piece and its ghost as the last update drew them over the level
+    sent_overlay: Vec<CellUpdate>,
+    /// The state and the score the renderer was told last
+    sent_state: Option<GameState>,
+    sent_score: Option<usize>,
+    pub ui_slave: Option<GameBroadcaster<GameEvent>>,
+    pub ui_listener: Option<GameUpdateReceiver<UiEvent>>,
 }
 
 impl Controller for EvilGameMaster {
@@ -34,15 +98,15 @@ impl Controller for EvilGameMaster {
         self.process_game();
     }
 
-    fn give_ui_broadcaster(&mut self, broadcaster: GameBroadcaster<Master2UICommunique>) {
+    fn give_ui_broadcaster(&mut self, broadcaster: GameBroadcaster<GameEvent>) {
         self.ui_slave = Some(broadcaster);
     }
 
-    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<Master2RenderCommunique>) {
-        self.render_slave = Some(broadcaster);
+    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<GameEvent>) {
+        self.give_render_slave(broadcaster);
     }
 
-    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UI2MasterCommunique>) {
+    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>) {
         self.ui_listener = Some(receiver);
     }
 }
@@ -65,22 +129,59 @@ impl EvilGameMaster {
         row.push(GameBlock::Indestructible);
         return row;
     }
-    pub fn new(height: usize, width: usize, initial_piece: Option<Piece>,
-               render_slave: Option<GameBroadcaster<Master2RenderCommunique>>,
-               ui_slave: Option<GameBroadcaster<Master2UICommunique>>,
-               ui_listener: Option<GameUpdateReceiver<UI2MasterCommunique>>) -> Self {
+    pub fn new(config: &GameConfig, initial_piece: Option<Piece>,
+               render_slave: Option<GameBroadcaster<GameEvent>>,
+               ui_slave: Option<GameBroadcaster<GameEvent>>,
+               ui_listener: Option<GameUpdateReceiver<UiEvent>>) -> Self {
+        let clock = RealClock::shared();
+        let clock_start_ms = clock.now_ms();
+        let level = Self::create_level(config.width, config.height);
         let mut s = EvilGameMaster {
-            level: Self::create_level(width, height),
+            occupancy: Occupancy::from_level(&level),
+            level,
             completed_rows: Vec::new(),
             active_piece: initial_piece,
             speed: 1,
             score: 0,
-            num_pieces: 0,
+            lines: 0,
+            stats: GameStats::new(),
+            mode: config.mode,
+            randomizer: Randomizer::Random,
+            config: *config,
+            countdown_ms: config.countdown_ms,
+            soft_drop_factor: OPTION_SOFT_DROP_FACTOR,
+            soft_drop: false,
+            lock_delay_frames: config.lock_delay_frames,
+            are_frames: config.are_frames,
+            line_clear_frames: config.line_clear_frames,
+            ghost: OPTION_GHOST,
+            preview: OPTION_PREVIEW,
             piece_map: PieceDefinitions::new(),
-            piece_bucket: Vec::with_capacity(OPTION_BUCKET_MAX_SIZE),
-            sw: Stopwatch::new(),
+            piece_bucket: Vec::with_capacity(config.bucket_size),
+            rng: Self::create_rng(config.seed),
+            clock,
+            clock_start_ms,
+            frame: 0,
+            game_frames: 0,
+            countdown_frames: 0,
+            fall_frames: 0,
+            lock_frames: 0,
+            lock_resets: 0,
+            entry_frames: 0,
+            clear_frames: 0,
+            hud_frames: 0,
+            held_events: Vec::new(),
+            replay: Replay::new(config.width, config.height),
+            replay_file: None,
+            replay_frames: 0,
+            replay_frame: 0,
             state: GameState::Start,
             render_slave,
+            board_sent: false,
+            dirty_cells: Vec::new(),
+            sent_overlay: Vec::new(),
+            sent_state: None,
+            sent_score: None,
             ui_slave,
             ui_listener,
             gom: None,
@@ -89,7 +190,7 @@ impl EvilGameMaster {
         return s;
     }
     fn next_piece(&mut self) -> bool {
-        if self.piece_bucket.len() < OPTION_BUCKET_MINIMUM_SIZE {
+        if self.piece_bucket.len() < OPTION_BUCKET_MINIMUM_SIZE.max(self.preview + 1) {
             self.fill_piece_bucket();
         }
         self.active_piece = self.piece_bucket.pop();
@@ -102,16 +203,32 @@ impl EvilGameMaster {
     }
 
     fn fill_piece_bucket(&mut self) {
-        for _ in self.piece_bucket.len()..OPTION_BUCKET_MAX_SIZE {
-            self.piece_bucket.push(
-                Piece::of_type(self.random_piece_type(),
-                               Self::random_color(),
-                               Point { y: 1, x: self.level[0].len() / 2 }));
+        let mut fresh: Vec<&'static str> = Vec::new();
+        while self.piece_bucket.len() + fresh.len() < self.config.bucket_size {
+            match self.randomizer {
+                Randomizer::Random => fresh.push(self.random_piece_type()),
+                Randomizer::Bag => {
+                    let mut bag = PLAYABLE_PIECES.to_vec();
+                    bag.shuffle(&mut self.rng);
+                    fresh.append(&mut bag);
+                }
+            }
         }
+        //pieces are popped off the back, so the fresh ones go in front of what is left
+        let mut bucket: Vec<Piece> = fresh.iter().rev().map(|kind| self.spawn_piece(kind)).collect();
+        bucket.append(&mut self.piece_bucket);
+        self.piece_bucket = bucket;
+    }
+
+    fn spawn_piece(&mut self, kind: &str) -> Piece {
+        let color = self.random_color();
+        Piece::of_type(self.piece_map.get_piece_def(kind),
+                       color,
+                       Point { y: 1, x: self.level[0].len() / 2 })
     }
 
-    fn random_color() -> BlockColor {
-        let rand_num = thread_rng().gen_range(0..=5);
+    fn random_color(&mut self) -> BlockColor {
+        let rand_num = self.rng.gen_range(0..=5);
         match rand_num {
             0 => BlockColor::Blue,
             1 => BlockColor::Green,
@@ -123,125 +240,265 @@ impl EvilGameMaster {
         }
     }
 
-    fn random_piece_type(&self) -> &PieceDefinition {
-        let rand_num = thread_rng().gen_range(0..=6);
-        match rand_num {
-            0 => self.piece_map.get_piece_def(PIECE_LINE),
-            1 => self.piece_map.get_piece_def(PIECE_SQUARE),
-            2 => self.piece_map.get_piece_def(PIECE_PODIUM),
-            3 => self.piece_map.get_piece_def(PIECE_L),
-            4 => self.piece_map.get_piece_def(PIECE_J),
-            5 => self.piece_map.get_piece_def(PIECE_S),
-            6 => self.piece_map.get_piece_def(PIECE_Z),
-            _ => panic!("random piece out of range")
+    fn random_piece_type(&mut self) -> &'static str {
+        PLAYABLE_PIECES[self.rng.gen_range(0..PLAYABLE_PIECES.len())]
+    }
+
+    /// Seeded games deal the same pieces every time they start over
+    fn create_rng(seed: Option<u64>) -> StdRng {
+        match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
         }
     }
 
+    /**
+    * Handles the input, then runs the frames that are due by the clock. It can be called as
+    * often as the caller likes: the game only moves in whole frames, so it plays the same
+    * however fast or slow this is called.
+    */
     pub fn process_game(&mut self) -> bool {
-        let mut should_update_render = false;
-        let mut should_continue = true;
+        let shows_input = matches!(self.state, GameState::Playing | GameState::Countdown(_));
+        let mut should_update_render = self.process_input_commands() && shows_input;
+        let due = self.frames_due();
+        while self.frame < due && self.state != Exit {
+            self.frame += 1;
+            if self.advance_frame() {
+                should_update_render = true;
+            }
+        }
+        if should_update_render {
+            self.send_render_update();
+        }
+        self.state != Exit
+    }
 
-        match self.state {
-            GameState::Playing => {
-                if self.active_piece.is_none() {
-                    if !self.next_piece() {
-                        self.state = GameState::End;
-                        self.active_piece = None;
-                        return should_continue;
-                    }
-                }
-                if self.next_tick() {
-                    should_update_render = true;
+    /// Runs one frame right away, whatever the clock says
+    pub fn step(&mut self) -> bool {
+        if self.advance_frame() {
+            self.send_render_update();
+        }
+        self.state != Exit
+    }
 
-                    if !self.advance_active_piece() {
-                        self.freeze_active_piece();
-                        if !self.completed_rows.is_empty() {
-                            self.state = Tootris;
-                        }
-                        //in this case, return to immediately "instantiate" next piece
-                        //or handle the tootris state
-                        return should_continue; //in the next loop cycle
-                    }
-                }
-                //todo: check UI input
-                if self.process_input_commands() {
-                    should_update_render = true;
+    /// Frames the clock has run by now. Skips what is too far behind to catch up with.
+    fn frames_due(&mut self) -> u64 {
+        let due = self.clock.now_ms().saturating_sub(self.clock_start_ms) * OPTION_FRAME_RATE / 1000;
+        if due > self.frame + OPTION_MAX_CATCH_UP_FRAMES {
+            self.frame = due - OPTION_MAX_CATCH_UP_FRAMES;
+        }
+        due
+    }
+
+    /**
+    * How long until the clock runs the next frame, None while no frame would change anything
+    * (in the menus, paused, after the game) so the caller can wait for input instead
+    */
+    pub fn next_frame_in(&self) -> Option<Duration> {
+        if !self.frames_running() {
+            return None;
+        }
+        let due_ms = self.clock_start_ms + ((self.frame + 1) * 1000).div_ceil(OPTION_FRAME_RATE);
+        Some(Duration::from_millis(due_ms.saturating_sub(self.clock.now_ms())))
+    }
+
+    fn frames_running(&self) -> bool {
+        matches!(self.state, GameState::Playing | GameState::Tootris | GameState::Countdown(_) | GameState::Replay)
+    }
+
+    /**
+    * Counts the frames from now on. Nothing calls process_game while no frames run, so the time
+    * spent in the menus, paused or after the game would otherwise be caught up on all at once.
+    */
+    fn restart_frames(&mut self) {
+        self.clock_start_ms = self.clock.now_ms();
+        self.frame = 0;
+    }
+
+    /**
+    * One frame of the game, returns true if the level changed
+    */
+    fn advance_frame(&mut self) -> bool {
+        match self.state {
+            GameState::Playing => self.play_frame(),
+            GameState::Tootris => {
+                self.game_frames += 1;
+                self.clear_frames += 1;
+                if self.clear_frames > self.line_clear_frames {
+                    self.clear_completed_rows();
+                    return true;
                 }
+                false
             }
-            GameState::Tootris => {
-                if self.next_tick() {
-                    self.send_state_to_ui();
-                    self.score += (self.completed_rows.len() * self.level[0].len())
-                        .mul(self.completed_rows.len());
-
-                    let mut new_matrix: GameMatrix = Vec::with_capacity(self.level.len());
-                    if self.speed < 99 - self.completed_rows.len() {
-                        self.speed+= self.completed_rows.len();
-                    } else {
-                        self.speed = 99;
-                    }
-
-                    //in new matrix, create new empty rows at the top
-                    for _new_row in 0..self.completed_rows.len() {
-                        new_matrix.push(Self::create_empty_row(self.level[0].len()))
-                    }
-                    //in new matrix, add the remaining rows from the old
-                    for i in 0..self.level.to_owned().len() {
-                        if self.completed_rows.contains(&i) {
-                            continue;
-                        }
-                        new_matrix.push(self.level[i].to_owned());
-                    }
-                    self.level = new_matrix;
-                    self.completed_rows = Vec::new();
-                    self.state = GameState::Playing;
-                    should_update_render = true;
+            GameState::Countdown(phase) => {
+                self.countdown_frames += 1;
+                let length = ms_to_frames(self.countdown_ms);
+                if self.countdown_frames >= length {
+                    self.start_playing();
+                } else if phase == Countdown::Ready
+                    && self.countdown_frames >= length.saturating_sub(ms_to_frames(OPTION_COUNTDOWN_GO_MS)) {
+                    self.state = GameState::Countdown(Countdown::Go);
                     self.send_state_to_ui();
+                    return true;
                 }
+                false
             }
+            GameState::Replay => {
+                self.play_replay();
+                false
+            }
+            GameState::Paused | GameState::End | GameState::Start | GameState::Exit => false,
+        }
+    }
 
-            GameState::Paused => {
-                self.process_input_commands();
+    fn play_frame(&mut self) -> bool {
+        self.game_frames += 1;
+        if self.mode.time_limit_ms().is_some_and(|limit| self.game_time_ms() >= limit) {
+            self.end_game();
+            return false;
+        }
+        if self.hud_frames == 0 {
+            self.send_state_to_ui();
+        }
+        self.hud_frames = (self.hud_frames + 1) % ms_to_frames(OPTION_HUD_REFRESH_MS).max(1);
+
+        let mut changed = false;
+        if self.active_piece.is_none() {
+            if self.entry_frames > 0 {
+                self.entry_frames -= 1;
+                return false;
             }
-            GameState::End => {
-                if !self.sw.is_running() {
-                    self.sw.start();
-                }
-                if self.next_tick() {
-                    if self.active_piece.is_none() {
-                        self.active_piece = Some(Piece
-                        ::new(PieceDefinitions::new().get_piece_def(
-                            GAME_OVER_PIECE.as_ref()).def.to_owned(),
-                              PieceFreezeProperty::Normal,
-                              BlockColor::Magenta, Point { x: 5, y: self.level.len() - 1 }));
-                    }
-                    self.send_render_update(Some(ControllerCommand::FullRefresh));
-                }
-                self.process_input_commands();
+            if !self.next_piece() {
+                self.end_game();
+                return false;
+            }
+            changed = true;
+        }
+        if self.soft_drop && self.soft_drop_factor == 0 {
+            //sonic drop, straight down but the piece still waits out its lock delay
+            while self.vertical_move(1) {
+                changed = true;
             }
-            GameState::Start => {
-                self.process_input_commands();
+        }
+        if self.is_grounded() {
+            self.fall_frames = 0;
+            self.lock_frames += 1;
+            if self.lock_frames > self.lock_delay_frames {
+                self.lock_active_piece();
+                return true;
             }
-            GameState::Exit => {
-                should_continue = false;
+        } else {
+            self.lock_frames = 0;
+            self.fall_frames += 1;
+            if self.fall_frames >= self.fall_delay() {
+                self.fall_frames = 0;
+                if self.advance_active_piece() {
+                    changed = true;
+                }
             }
         }
-        if should_update_render {
-            self.send_render_update(None);
+        changed
+    }
+
+    /// Scores the completed rows and lets the rows above them fall in their place
+    fn clear_completed_rows(&mut self) {
+        self.send_state_to_ui();
+        self.score += (self.completed_rows.len() * self.level[0].len())
+            .mul(self.completed_rows.len());
+        self.lines += self.completed_rows.len();
+        self.send_to_ui(vec![GameEvent::LinesCleared(self.completed_rows.len())]);
+        self.stats.lines_cleared(self.completed_rows.len());
+
+        if self.speed < 99 - self.completed_rows.len() {
+            self.speed+= self.completed_rows.len();
+        } else {
+            self.speed = 99;
+        }
+
+        //going top first, taking a row out and putting an empty one in at the top leaves the rows
+        //below it where they were
+        self.completed_rows.sort_unstable();
+        for row in self.completed_rows.iter() {
+            self.level.remove(*row);
+            self.level.insert(0, Self::create_empty_row(self.level[0].len()));
+        }
+        //everything above the lowest cleared row moved down
+        let width = self.level[0].len();
+        if let Some(lowest) = self.completed_rows.last() {
+            self.dirty_cells.extend((0..=*lowest).flat_map(|y| (0..width).map(move |x| Point { x, y })));
+        }
+        self.occupancy.collapse(&self.completed_rows);
+        self.completed_rows = Vec::new();
+        self.state = GameState::Playing;
+        self.send_state_to_ui();
+        if self.mode.goal_lines().is_some_and(|goal| self.lines >= goal) {
+            self.end_game();
         }
-        return should_continue;
     }
 
+    /// Takes in everything the ui sent, true if any of it changed what is shown
     fn process_input_commands(&mut self) -> bool {
-        if self.ui_listener.is_some() {
-            let mut command = self.ui_listener.as_mut().unwrap().receiver.try_recv();
-            if command.is_err() {
-                return false;
+        let mut changed = false;
+        while !self.held_events.is_empty() && !self.waiting_for_piece()
+            && self.state != GameState::Paused && self.state != Exit {
+            let event = self.held_events.remove(0);
+            if self.process_ui_event(event) {
+                changed = true;
+            }
+        }
+        while self.state != Exit {
+            let event = match self.ui_listener.as_ref().and_then(|listener| listener.receiver.try_recv().ok()) {
+                Some(event) => event,
+                None => break,
+            };
+            //moves made while rows are cleared or before the next piece shows up are kept for it,
+            //anything else is taken right away unless it is an exit that has to wait for them
+            let held = match event {
+                UiEvent::Move(_) | UiEvent::AutoShift(_, _) => {
+                    self.waiting_for_piece() || !self.held_events.is_empty()
+                }
+                UiEvent::Command(UiCommand::Exit) => !self.held_events.is_empty(),
+                _ => false,
+            };
+            if held {
+                self.held_events.push(event);
+            } else if self.process_ui_event(event) {
+                changed = true;
             }
-            if command.as_ref().unwrap().is_command() {
-                match command.as_mut().unwrap().command.as_ref().unwrap() {
+        }
+        changed
+    }
+
+    fn waiting_for_piece(&self) -> bool {
+        self.state == Tootris || (self.state == GameState::Playing && self.active_piece.is_none())
+    }
+
+    fn process_ui_event(&mut self, event: UiEvent) -> bool {
+        match event {
+            UiEvent::Command(command) => {
+                match command {
                     UiCommand::New => {
                         self.new_game();
+                        self.resume_game();
+                    }
+                    UiCommand::Menu => {
+                        self.new_game();
+                    }
+                    UiCommand::Replay => {
+                        self.start_replay();
+                    }
+                    UiCommand::StopReplay => {
+                        self.stop_replay();
+                    }
+                    UiCommand::SoftDrop(held) => {
+                        self.soft_drop = held;
+                    }
+                    UiCommand::Handling(handling) => {
+                        self.soft_drop_factor = handling.soft_drop_factor;
+                        self.ghost = handling.ghost;
+                        self.preview = handling.preview;
+                        self.send_render_update();
+                        self.send_state_to_ui();
                     }
                     UiCommand::Pause => {
                         self.pause_game();
@@ -251,135 +508,330 @@ impl EvilGameMaster {
                     }
                     UiCommand::Exit => {
                         self.state = Exit;
-                        self.send_render_update(None);
+                        self.send_render_update();
+                    }
+                    UiCommand::BoardSize(size) => {
+                        if self.state == GameState::Start || self.state == GameState::End {
+                            self.resize(size.x, size.y);
+                        }
+                    }
+                    UiCommand::Randomizer(randomizer) => {
+                        self.randomizer = randomizer;
+                        self.piece_bucket.clear();
+                    }
+                    UiCommand::Start(mode) => {
+                        self.mode = mode;
+                        self.new_game();
+                        self.resume_game();
                     }
-                    _ => {}
                 }
                 return true;
             }
-
-            if command.as_ref().unwrap().is_player_move() {
-                return self.process_move(command.as_ref().unwrap().player_move.as_ref().unwrap());
+            UiEvent::Move(player_move) => {
+                //only rotation can be prepared during the countdown
+                if matches!(self.state, GameState::Countdown(_)) && !player_move.is_rotation() {
+                    return false;
+                }
+                self.stats.input();
+                return self.process_move(&player_move);
             }
-            return false;
+            UiEvent::AutoShift(player_move, steps) => {
+                if matches!(self.state, GameState::Countdown(_)) {
+                    return false;
+                }
+                let mut moved = false;
+                for _ in 0..steps {
+                    if !self.process_move(&player_move) {
+                        break;
+                    }
+                    moved = true;
+                }
+                return moved;
+            }
+            //the rest is for the renderer
+            _ => {}
         }
-        return false;
+        false
+    }
+
+    /**
+    * Runs the game on another clock, e.g. a ManualClock in tests. The frames are counted
+    * from what the new clock says now.
+    */
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+        self.restart_frames();
+    }
+
+    /// Frames between gravity steps at the current speed
+    pub fn gravity_frames(&self) -> u64 {
+        ms_to_frames(((100 - self.speed) * self.config.tick_base_ms) as u64).max(1)
     }
 
-    fn next_tick(&mut self) -> bool {
-        let tick_ms = ((100 as usize - self.speed) * OPTION_TICK_BASE_MS) as i64;
-        if tick_ms <= self.sw.elapsed_ms() {
-            self.sw.restart();
-            return true;
+    /// Frames between gravity steps, fewer while soft drop is held
+    fn fall_delay(&self) -> u64 {
+        if self.soft_drop && self.soft_drop_factor > 0 {
+            return (self.gravity_frames() / self.soft_drop_factor as u64).max(1);
         }
-        return false;
+        self.gravity_frames()
+    }
+
+    /// Time actually spent playing, excluding pauses
+    pub fn game_time_ms(&self) -> u64 {
+        frames_to_ms(self.game_frames)
     }
 
     pub fn exit(&mut self) {
-        if self.sw.is_running() {
-            self.sw.stop();
-        }
         self.state = GameState::Exit;
     }
 
     pub fn new_game(&mut self) {
-        if self.sw.is_running() {
-            self.sw.stop();
-        }
-        self.sw.reset();
+        self.game_frames = 0;
+        self.fall_frames = 0;
+        self.lock_frames = 0;
+        self.lock_resets = 0;
+        self.entry_frames = 0;
+        self.hud_frames = 0;
         self.score = 0;
+        self.lines = 0;
+        self.stats = GameStats::new();
+        self.speed = 1;
+        self.soft_drop = false;
         self.active_piece = None;
+        self.held_events.clear();
+        self.piece_bucket.clear();
+        self.rng = Self::create_rng(self.config.seed);
         self.level = Self::create_level(self.level[0].len(), self.level.len());
         self.create_level_boundaries();
+        self.replay = Replay::new(self.level[0].len(), self.level.len());
         self.state = GameState::Start;
+        self.restart_frames();
         self.send_state_to_ui();
+        self.send_render_update();
+    }
+
+    /// Width and height include the border. Throws away the current level.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.level = Self::create_level(width, height);
+        self.create_level_boundaries();
+        self.active_piece = None;
+        self.piece_bucket.clear();
+        self.send_render_update();
+        self.send_to_renderer(GameEvent::Refresh);
     }
 
+    /// Starts or resumes play, after the countdown if there is one
     pub fn resume_game(&mut self) {
-        if !self.sw.is_running() {
-            self.sw.start();
+        if !self.frames_running() {
+            self.restart_frames();
+        }
+        if self.countdown_ms == 0 {
+            self.start_playing();
+            return;
+        }
+        //the piece shows up during the countdown so it can be rotated ahead of time
+        if self.active_piece.is_none() && !self.next_piece() {
+            self.end_game();
+            return;
         }
+        self.countdown_frames = 0;
+        self.state = GameState::Countdown(Countdown::Ready);
+        self.send_state_to_ui();
+        self.send_render_update();
+    }
+
+    fn start_playing(&mut self) {
         self.state = GameState::Playing;
         self.send_state_to_ui();
+        self.send_render_update();
     }
 
+    /// Rows waiting to be cleared are cleared right away, resuming goes on with the next piece
     pub fn pause_game(&mut self) {
-        if self.sw.is_running() {
-            self.sw.stop();
+        if self.state == Tootris {
+            self.clear_completed_rows();
+            if self.state != GameState::Playing {
+                return;
+            }
         }
+        self.soft_drop = false;
         self.state = GameState::Paused;
         self.send_state_to_ui();
-        return;
+        self.send_render_update();
     }
 
-    pub fn give_render_slave(&mut self, broadcaster: GameBroadcaster<Master2RenderCommunique>) {
+    fn end_game(&mut self) {
+        self.state = GameState::End;
+        self.active_piece = None;
+        self.send_render_update();
+        if let Some(path) = self.replay_file.as_ref() {
+            if self.replay.save(path).is_err() && self.ui_slave.is_some() {
+                self.ui_warn("Could not save the replay");
+            }
+        }
+        let events = vec![GameEvent::StateChanged(self.state), GameEvent::Score(self.score),
+                          GameEvent::Hud(self.hud()), GameEvent::GameOver(self.result())];
+        if self.send_to_ui(events) {
+            eprintln!("Could not send game result to ui slave");
+        }
+    }
+
+    /// Shows a recording that was loaded from a file, instead of the last game
+    pub fn play_recording(&mut self, replay: Replay) {
+        self.resize(replay.width, replay.height);
+        self.replay = replay;
+        self.state = GameState::End;
+        self.start_replay();
+    }
+
+    fn start_replay(&mut self) {
+        if self.state != GameState::End || self.replay.is_empty() {
+            return;
+        }
+        self.restart_frames();
+        //the renderer gets the recording instead, and the whole level again after it
+        self.board_sent = false;
+        self.state = GameState::Replay;
+        self.replay_frame = 0;
+        self.replay_frames = 0;
+        self.send_state_to_ui();
+    }
+
+    /// Sends the frames that are due, and goes back to the end state after the last one
+    fn play_replay(&mut self) {
+        let elapsed = frames_to_ms(self.replay_frames);
+        self.replay_frames += 1;
+        let shown = self.replay_frame;
+        while self.replay_frame < self.replay.frames.len()
+            && self.replay.frames[self.replay_frame].time_ms <= elapsed {
+            self.replay_frame += 1;
+        }
+        if self.replay_frame > shown && self.render_slave.is_some() {
+            let frame = if shown == 0 {
+                GameEvent::Board(self.replay.level_at(self.replay_frame - 1))
+            } else {
+                GameEvent::BoardUpdate(self.replay.changes(shown - 1, self.replay_frame - 1))
+            };
+            if self.send_board(Some(frame)) {
+                eprintln!("Could not send replay frame to render slave");
+            }
+        }
+        //linger on the final frame for a moment before handing control back
+        if self.replay_frame >= self.replay.frames.len()
+            && elapsed >= self.replay.duration_ms() + OPTION_REPLAY_LINGER_MS {
+            self.stop_replay();
+        }
+    }
+
+    fn stop_replay(&mut self) {
+        if self.state != GameState::Replay {
+            return;
+        }
+        self.state = GameState::End;
+        self.send_render_update();
+        self.send_state_to_ui();
+    }
+
+    /// The next pieces out of the bucket, as many as the preview shows
+    pub fn upcoming(&self) -> Vec<Piece> {
+        self.piece_bucket.iter().rev().take(self.preview).cloned().collect()
+    }
+
+    /// Where the active piece would end up if dropped straight down
+    fn ghost_points(&self) -> Vec<Point> {
+        let piece = match self.active_piece.as_ref() {
+            Some(piece) => piece,
+            None => return Vec::new(),
+        };
+        let mut location = piece.location;
+        loop {
+            let below = Point { x: location.x, y: location.y + 1 };
+            if self.is_point_colliding(piece.points(Some(&below))) {
+                break;
+            }
+            location = below;
+        }
+        piece.points(Some(&location))
+    }
+
+    pub fn result(&self) -> GameResult {
+        GameResult {
+            mode: self.mode,
+            score: self.score,
+            lines: self.lines,
+            level: self.speed,
+            duration_ms: self.game_time_ms(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    pub fn hud(&self) -> HudInfo {
+        HudInfo {
+            elapsed_ms: self.game_time_ms(),
+            lines: self.lines,
+            level: self.speed,
+            pieces: self.stats.pieces,
+            inputs: self.stats.inputs,
+        }
+    }
+
+    pub fn give_render_slave(&mut self, broadcaster: GameBroadcaster<GameEvent>) {
         self.render_slave = Some(broadcaster);
+        self.board_sent = false;
+        self.sent_state = None;
+        self.sent_score = None;
     }
 
-    pub fn give_ui_slave(&mut self, broadcaster: GameBroadcaster<Master2UICommunique>) {
+    pub fn give_ui_slave(&mut self, broadcaster: GameBroadcaster<GameEvent>) {
         self.ui_slave = Some(broadcaster);
     }
 
-    pub fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UI2MasterCommunique>) {
+    pub fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>) {
         self.ui_listener = Some(receiver);
     }
 
     fn freeze_active_piece(&mut self) {
         self.active_piece.as_ref().unwrap().place_in_matrix(self.level.as_mut_slice());
+        let points = self.active_piece.as_ref().unwrap().points(None);
+        self.occupancy.fill(&points);
+        self.dirty_cells.extend(points);
+        let holes = self.occupancy.holes();
+        self.stats.piece_placed(self.active_piece.as_ref().unwrap().kind, &self.level, holes);
+        self.send_to_ui(vec![GameEvent::PieceLocked]);
         self.active_piece = None;
+        self.fall_frames = 0;
+        self.lock_frames = 0;
+        self.lock_resets = 0;
+        self.entry_frames = self.are_frames;
         self.find_completed_rows();
     }
 
-    fn find_completed_rows(&mut self) {
-        'rows: for row in 0..self.level.len() {
-            let mut begin = false;
-            let mut end = false;
-
-            for cell in self.level[row].as_slice() {
-                match cell {
-                    &GameBlock::Indestructible => {
-                        if end {
-                            //this must be a border row
-                            continue 'rows;
-                        }
-                        if begin { //If it's not the beginning it must be the end
-                            end = true;
-                        } else { //If it's not the end then it must be the beginning
-                            begin = true;
-                        }
-                    }
-                    &GameBlock::Filled(_) => {
-                        if end {
-                            self.ui_warn("Invalid state: piece block appears after border");
-                        } else if begin {
-                            //seems legit
-                        } else {
-                            self.ui_warn("Invalid state: piece block appears before border");
-                        }
-                    }
-                    _ => {
-                        continue 'rows;
-                    }
-                }
-            }
-            //If we get here, the row has only filled blocks
-            if begin && end {
-                self.completed_rows.push(row);
-            }
+    fn lock_active_piece(&mut self) {
+        self.freeze_active_piece();
+        if !self.completed_rows.is_empty() {
+            self.clear_frames = 0;
+            self.state = Tootris;
+            self.send_state_to_ui();
         }
     }
 
+    /// True if the active piece can't fall any further
+    fn is_grounded(&self) -> bool {
+        self.active_piece.as_ref().is_some_and(|piece| {
+            let below = Point { x: piece.location.x, y: piece.location.y + 1 };
+            self.is_point_colliding(piece.points(Some(&below)))
+        })
+    }
+
+    fn find_completed_rows(&mut self) {
+        self.completed_rows = self.occupancy.full_rows();
+    }
+
     fn ui_warn(&self, msg: &'static str) {
         if self.ui_slave.is_none() {
             eprintln!("No ui slave!");
         }
-        let result = self.ui_slave.as_ref().unwrap().channel_out.send(Master2UICommunique {
-            comm_type: Communique::Info(msg),
-            state: None,
-            score: None,
-        });
-
-        if result.is_err() {
+        if self.send_to_ui(vec![GameEvent::Info(msg)]) {
             eprintln!("Could not send message to ui slave");
         }
     }
@@ -392,19 +844,14 @@ impl EvilGameMaster {
         return self.is_point_colliding(piece.find_yboundaries(point_override));
     }
 
-    pub(crate) fn is_point_colliding(&self, bounds: Vec<Point>) -> bool {
-        for point in bounds {
-            if point.y >= self.level.len() {
-                return true;
-            }
-            if point.x >= self.level[0].len() {
-                return true;
-            }
-            if self.level[point.y][point.x] != GameBlock::Empty {
-                return true;
-            }
-        }
-        return false;
+    pub fn is_point_colliding(&self, bounds: Vec<Point>) -> bool {
+        self.occupancy.collides(&bounds)
+    }
+
+    /// Takes the occupancy from the level again, for when the level was changed from outside
+    pub fn sync_occupancy(&mut self) {
+        self.occupancy = Occupancy::from_level(&self.level);
+        self.board_sent = false;
     }
 
     /**
@@ -417,14 +864,39 @@ impl EvilGameMaster {
         return false;
     }
 
+    /**
+    * Moving or rotating a piece that rests on the stack starts its lock delay over, up to
+    * OPTION_LOCK_RESETS times per piece
+    */
     pub fn process_move(&mut self, mov: &PlayerMove) -> bool {
-        match mov {
+        let moved = match mov {
             PlayerMove::RotateForward => self.rotate_active_piece(&Rotation::Forward),
             PlayerMove::StepLeft => self.horizontal_move(1, true),
             PlayerMove::StepRight => self.horizontal_move(1, false),
             PlayerMove::StepDown => self.vertical_move(1),
-            _ => false,
+            PlayerMove::OrientUp => self.rotate_active_piece(&Rotation::OrientUp),
+            PlayerMove::OrientDown => self.rotate_active_piece(&Rotation::OrientDown),
+            PlayerMove::OrientLeft => self.rotate_active_piece(&Rotation::OrientLeft),
+            PlayerMove::OrientRight => self.rotate_active_piece(&Rotation::OrientRight),
+            PlayerMove::HardDrop => self.hard_drop(),
+        };
+        if moved && self.lock_frames > 0 && self.lock_resets < OPTION_LOCK_RESETS {
+            self.lock_frames = 0;
+            self.lock_resets += 1;
+        }
+        moved
+    }
+
+    /**
+    * Drops the active piece to the floor and locks it without waiting for the lock delay
+    */
+    fn hard_drop(&mut self) -> bool {
+        if self.active_piece.is_none() || self.state != GameState::Playing {
+            return false;
         }
+        while self.vertical_move(1) {}
+        self.lock_active_piece();
+        true
     }
 
     fn vertical_move(&mut self, amount: usize) -> bool {
@@ -461,7 +933,8 @@ impl EvilGameMaster {
         if self.active_piece.is_some() {
             self.active_piece.as_mut().unwrap().rotate(rot);
 
-            if self.is_point_colliding(self.active_piece.as_ref().unwrap().points(None)) {
+            let sticks_out = self.active_piece.as_ref().is_some_and(|piece| piece.sticks_out(None));
+            if sticks_out || self.is_point_colliding(self.active_piece.as_ref().unwrap().points(None)) {
                 self.active_piece.as_mut().unwrap().rollback_rotation();
                 return false;
             }
@@ -480,34 +953,116 @@ impl EvilGameMaster {
                 }
             }
         }
+        self.sync_occupancy();
     }
 
     fn send_state_to_ui(&mut self) -> bool {
-        if self.ui_slave.is_some() {
-            return self.ui_slave.as_ref().unwrap().channel_out.send(Master2UICommunique {
-                comm_type: Update,
-                state: Some(self.state.clone()),
-                score: Some(self.score),
-            }).is_err();
+        if self.ui_slave.is_none() {
+            return false;
         }
-        return false;
+        self.send_to_ui(vec![GameEvent::StateChanged(self.state), GameEvent::Score(self.score),
+                             GameEvent::Hud(self.hud()), GameEvent::Preview(self.upcoming())])
     }
 
-    fn send_render_update(&mut self, command: Option<ControllerCommand>) -> bool {
-        if self.render_slave.is_some() {
-            let mut level_update = self.level.clone();
-            if self.active_piece.is_some() {
-                self.active_piece.as_ref().unwrap().place_in_matrix(level_update.as_mut_slice());
-            }
+    /// Sends the events to the ui in order, true if it couldn't take them
+    fn send_to_ui(&self, events: Vec<GameEvent>) -> bool {
+        let ui = match self.ui_slave.as_ref() {
+            Some(ui) => ui,
+            None => return false,
+        };
+        events.into_iter().any(|event| ui.send(event).is_err())
+    }
+
+    /// True if the renderer couldn't take the event
+    fn send_to_renderer(&self, event: GameEvent) -> bool {
+        self.render_slave.as_ref().is_some_and(|renderer| renderer.send(event).is_err())
+    }
 
-            return self.render_slave.as_ref().unwrap().channel_out.send(Master2RenderCommunique {
-                comm_type: Communique::Update,
-                level: Some(level_update),
-                state: Some(self.state.clone()),
-                score: Some(self.score),
-                command,
-            }).is_err();
+    pub fn send_render_update(&mut self) -> bool {
+        let recording = self.state == GameState::Playing || self.state == GameState::Tootris;
+        if self.render_slave.is_none() && !recording {
+            self.dirty_cells.clear();
+            return false;
         }
-        return false;
+        let piece: Vec<CellUpdate> = self.active_piece.as_ref().map_or(Vec::new(), |piece| {
+            piece.points(None).into_iter()
+                .map(|at| CellUpdate { at, block: GameBlock::Filled(piece.color) })
+                .collect()
+        });
+        if recording {
+            self.replay.record(self.game_time_ms(), &self.level, &piece);
+        }
+        if self.render_slave.is_none() {
+            self.dirty_cells.clear();
+            return false;
+        }
+        let mut overlay: Vec<CellUpdate> = Vec::new();
+        if let Some(color) = self.active_piece.as_ref().filter(|_| self.ghost).map(|piece| piece.color) {
+            overlay.extend(self.ghost_points().into_iter()
+                .filter(|at| !piece.iter().any(|cell| cell.at == *at))
+                .map(|at| CellUpdate { at, block: GameBlock::Ghost(color) }));
+        }
+        overlay.extend(piece);
+
+        let update = if self.board_sent {
+            let changes = self.overlay_changes(&overlay);
+            if changes.is_empty() { None } else { Some(GameEvent::BoardUpdate(changes)) }
+        } else {
+            let mut level = self.level.clone();
+            apply_changes(&mut level, &overlay);
+            Some(GameEvent::Board(level))
+        };
+        self.dirty_cells.clear();
+        self.sent_overlay = overlay;
+        let failed = self.send_board(update);
+        self.board_sent = !failed;
+        failed
+    }
+
+    /**
+    * The cells that look different since the last update: the ones the level changed, and the
+    * ones the piece and its ghost left or entered
+    */
+    fn overlay_changes(&self, overlay: &[CellUpdate]) -> Vec<CellUpdate> {
+        let mut points: Vec<Point> = self.dirty_cells.iter().copied()
+            .chain(self.sent_overlay.iter().map(|cell| cell.at))
+            .chain(overlay.iter().map(|cell| cell.at))
+            .collect();
+        points.sort_unstable_by_key(|point| (point.y, point.x));
+        points.dedup();
+        points.into_iter()
+            .filter_map(|at| {
+                let block = overlay.iter().find(|cell| cell.at == at)
+                    .map_or_else(|| self.level[at.y][at.x].clone(), |cell| cell.block.clone());
+                let unchanged = !self.dirty_cells.contains(&at)
+                    && self.sent_overlay.iter().any(|cell| cell.at == at && cell.block.same_look(&block));
+                if unchanged { None } else { Some(CellUpdate { at, block }) }
+            })
+            .collect()
+    }
+
+    /**
+    * Sends the state and the score if they changed since the renderer was last told, then the
+    * level or the cells of it that changed. True on errors.
+    */
+    fn send_board(&mut self, update: Option<GameEvent>) -> bool {
+        let mut failed = false;
+        if self.sent_state != Some(self.state) {
+            failed |= self.send_to_renderer(GameEvent::StateChanged(self.state));
+            self.sent_state = Some(self.state);
+        }
+        if self.sent_score != Some(self.score) {
+            failed |= self.send_to_renderer(GameEvent::Score(self.score));
+            self.sent_score = Some(self.score);
+        }
+        if let Some(update) = update {
+            failed |= self.send_to_renderer(update);
+        }
+        if failed {
+            self.board_sent = false;
+            self.sent_state = None;
+            self.sent_score = None;
+        }
+        failed
     }
 }
@@ -2,3 +2,9 @@ pub mod tootris;
 pub mod piece_types;
 pub mod piece;
 pub mod game_loop_controller;
+pub mod stats;
+pub mod replay;
+pub mod bot;
+pub mod bitboard;
+pub mod clock;
+pub mod protocol;
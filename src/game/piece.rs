@@ -5,6 +5,8 @@ use std::fmt::Formatter;
 
 #[derive(Clone)]
 pub struct Piece {
+    /// Name of the definition the piece was made from, if any
+    pub kind: Option<&'static str>,
     definition: Vec<Vec<DefinitionBlock>>,
     pub freeze_property: PieceFreezeProperty,
     pub current_matrix: Vec<Vec<DefinitionBlock>>,
@@ -47,6 +49,7 @@ impl Piece {
                freeze_property: PieceFreezeProperty, color: BlockColor, location: Point)
                -> Self {
         let mut fresh_self = Piece {
+            kind: None,
             definition,
             freeze_property,
             current_matrix: Vec::new(),
@@ -62,21 +65,23 @@ impl Piece {
     }
 
     pub fn of_type(t: &PieceDefinition, color: BlockColor, location: Point) -> Self {
-        Self::new(t.def.clone(), t.prop.clone(), color, location)
+        let mut piece = Self::new(t.def.clone(), t.prop, color, location);
+        piece.kind = Some(t.name);
+        piece
     }
 
-    pub(crate) fn rotate(&mut self, rot: &Rotation) {
+    pub fn rotate(&mut self, rot: &Rotation) {
         self.rollback_orientation = self.orientation;
         self.orientation = rot.perform(&self.rollback_orientation);
         self.update_current_matrix();
     }
 
-    pub(crate) fn rollback_rotation(&mut self) {
+    pub fn rollback_rotation(&mut self) {
         self.orientation = self.rollback_orientation;
         self.update_current_matrix();
     }
 
-    pub(crate) fn move_to(&mut self, new_location: Point) {
+    pub fn move_to(&mut self, new_location: Point) {
         self.rollback_location = self.location;
         self.location = new_location;
     }
@@ -188,6 +193,20 @@ impl Piece {
         };
     }
 
+    /// True if the origin is too close to the top or left edge for the whole piece to fit,
+    /// which can happen right after a rotation near the top of the level
+    pub fn sticks_out(&self, point_override: Option<&Point>) -> bool {
+        let location = point_override.unwrap_or(&self.location);
+        for y in 0..self.current_matrix.len() {
+            for x in 0..self.current_matrix[y].len() {
+                if self.current_matrix[y][x] == DefinitionBlock::Origin {
+                    return x > location.x || y > location.y;
+                }
+            }
+        }
+        false
+    }
+
     fn find_origin_for_zero_block(&self, point_override: Option<&Point>) -> Point {
         let mut origin: Option<Point> = None;
         'outer: for y in 0..self.current_matrix.len() {
@@ -232,7 +251,7 @@ impl Piece {
         return boundaries;
     }
 
-    pub(crate) fn find_xboundaries(&self, point_override: Option<&Point>) -> Vec<Point> {
+    pub fn find_xboundaries(&self, point_override: Option<&Point>) -> Vec<Point> {
         let origin = self.find_origin_for_zero_block(point_override);
         let mut boundaries: Vec<Point> = Vec::with_capacity(self.current_matrix.len() * 2);
 
@@ -266,7 +285,7 @@ impl Piece {
         return boundaries;
     }
 
-    pub(crate) fn find_yboundaries(&self, point_override: Option<&Point>) -> Vec<Point> {
+    pub fn find_yboundaries(&self, point_override: Option<&Point>) -> Vec<Point> {
         /*
          * We only ever need to check for y collision downwards, so we want one block for each x
          * The highest y value for each x
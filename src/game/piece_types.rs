@@ -25,6 +25,10 @@ macro_rules! double_vec {
     };
 }
 
+/// The pieces dealt during a game, GAME_OVER_PIECE is only for show
+pub static PLAYABLE_PIECES: [&str; 7] = [PIECE_LINE, PIECE_SQUARE, PIECE_PODIUM, PIECE_L, PIECE_J,
+    PIECE_S, PIECE_Z];
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum PieceFreezeProperty {
     Normal,
@@ -66,20 +70,22 @@ impl DefinitionBlock {
 
 #[derive(Clone, PartialEq)]
 pub struct PieceDefinition {
+    pub name: &'static str,
     pub def: Vec<Vec<DefinitionBlock>>,
     pub prop: PieceFreezeProperty,
 }
 
 impl PieceDefinition {
-    pub fn new(def: Vec<Vec<DefinitionBlock>>, prop: PieceFreezeProperty) -> Self {
+    pub fn new(name: &'static str, def: Vec<Vec<DefinitionBlock>>, prop: PieceFreezeProperty) -> Self {
         PieceDefinition {
+            name,
             def,
             prop,
         }
     }
 }
 
-pub(crate) type PieceMap = HashMap<&'static str, PieceDefinition>;
+pub type PieceMap = HashMap<&'static str, PieceDefinition>;
 
 pub struct PieceDefinitions {
     piece_map: PieceMap,
@@ -95,14 +101,14 @@ impl PieceDefinitions {
 
     pub fn get_piece_map() -> PieceMap {
         let mut map = PieceMap::new();
-        map.insert(PIECE_LINE, PieceDefinition::new(double_vec!(LINE), PieceFreezeProperty::Normal));
-        map.insert(PIECE_SQUARE, PieceDefinition::new(double_vec!(SQUARE), PieceFreezeProperty::Normal));
-        map.insert(PIECE_PODIUM, PieceDefinition::new(double_vec!(PODIUM), PieceFreezeProperty::FreeSpin));
-        map.insert(PIECE_L, PieceDefinition::new(double_vec!(LPIECE), PieceFreezeProperty::Normal));
-        map.insert(PIECE_J, PieceDefinition::new(double_vec!(JPIECE), PieceFreezeProperty::Normal));
-        map.insert(PIECE_S, PieceDefinition::new(double_vec!(SPIECE), PieceFreezeProperty::Normal));
-        map.insert(PIECE_Z, PieceDefinition::new(double_vec!(ZPIECE), PieceFreezeProperty::Normal));
-        map.insert(GAME_OVER_PIECE, PieceDefinition::new(double_vec!(GAME_OVER), PieceFreezeProperty::Normal));
+        map.insert(PIECE_LINE, PieceDefinition::new(PIECE_LINE, double_vec!(LINE), PieceFreezeProperty::Normal));
+        map.insert(PIECE_SQUARE, PieceDefinition::new(PIECE_SQUARE, double_vec!(SQUARE), PieceFreezeProperty::Normal));
+        map.insert(PIECE_PODIUM, PieceDefinition::new(PIECE_PODIUM, double_vec!(PODIUM), PieceFreezeProperty::FreeSpin));
+        map.insert(PIECE_L, PieceDefinition::new(PIECE_L, double_vec!(LPIECE), PieceFreezeProperty::Normal));
+        map.insert(PIECE_J, PieceDefinition::new(PIECE_J, double_vec!(JPIECE), PieceFreezeProperty::Normal));
+        map.insert(PIECE_S, PieceDefinition::new(PIECE_S, double_vec!(SPIECE), PieceFreezeProperty::Normal));
+        map.insert(PIECE_Z, PieceDefinition::new(PIECE_Z, double_vec!(ZPIECE), PieceFreezeProperty::Normal));
+        map.insert(GAME_OVER_PIECE, PieceDefinition::new(GAME_OVER_PIECE, double_vec!(GAME_OVER), PieceFreezeProperty::Normal));
 
         return map;
     }
@@ -116,6 +122,12 @@ impl PieceDefinitions {
     }
 }
 
+impl Default for PieceDefinitions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub static SQUARE: [[DefinitionBlock; 2]; 2] = [
     [Origin, Origin],
     [Origin, Origin]];
@@ -0,0 +1,196 @@
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, SendError, Sender, TryIter};
+use std::time::Duration;
+
+use crate::game::piece::Piece;
+use crate::game::tootris::{GameBlock, GameMatrix, GameResult, GameState, HudInfo, PlayerMove, Point, UiCommand};
+
+/**
+* Wrappers for communication between the components (UI, Renderer, controller..)
+*/
+#[derive(Clone)]
+pub struct GameBroadcaster<T> {
+    pub channel_out: Sender<T>,
+    /// Rung after every message, for a receiver that sleeps until there is something to do
+    pub wakeup: Option<Wakeup>,
+}
+
+impl<T> GameBroadcaster<T> {
+    pub fn new(channel_out: Sender<T>) -> Self {
+        GameBroadcaster { channel_out, wakeup: None }
+    }
+
+    pub fn with_wakeup(mut self, wakeup: Wakeup) -> Self {
+        self.wakeup = Some(wakeup);
+        self
+    }
+
+    pub fn send(&self, message: T) -> Result<(), SendError<T>> {
+        self.channel_out.send(message)?;
+        if let Some(wakeup) = self.wakeup.as_ref() {
+            wakeup.ring();
+        }
+        Ok(())
+    }
+}
+
+pub struct GameUpdateReceiver<T> {
+    pub receiver: Receiver<T>,
+}
+
+impl<T> GameUpdateReceiver<T> {
+    /// Everything sent so far, without waiting for more
+    pub fn pending(&self) -> TryIter<'_, T> {
+        self.receiver.try_iter()
+    }
+}
+
+/**
+* Lets a loop sleep until one of the channels it reads from has a message
+*/
+#[derive(Clone)]
+pub struct Wakeup {
+    ring: Sender<()>,
+}
+
+impl Wakeup {
+    pub fn ring(&self) {
+        //nobody is waiting anymore, nothing to wake
+        let _ = self.ring.send(());
+    }
+}
+
+pub struct WakeupReceiver {
+    rings: Receiver<()>,
+}
+
+impl WakeupReceiver {
+    /// Sleeps until rung or until the timeout is over, None waits for a ring however long it takes
+    pub fn wait(&self, timeout: Option<Duration>) {
+        match timeout {
+            Some(timeout) => {
+                if let Err(RecvTimeoutError::Disconnected) = self.rings.recv_timeout(timeout) {
+                    std::thread::sleep(timeout);
+                }
+            }
+            None => {
+                let _ = self.rings.recv();
+            }
+        }
+    }
+
+    /// Forgets the rings so far, for a loop about to read everything that was sent anyway
+    pub fn clear(&self) {
+        while self.rings.try_recv().is_ok() {}
+    }
+}
+
+pub fn wakeup_channel() -> (Wakeup, WakeupReceiver) {
+    let (ring, rings) = channel();
+    (Wakeup { ring }, WakeupReceiver { rings })
+}
+
+/// A cell of the level that changed, and what is in it now
+#[derive(Clone, PartialEq, Debug)]
+pub struct CellUpdate {
+    pub at: Point,
+    pub block: GameBlock,
+}
+
+/**
+* The cells that differ between the two levels, row by row. None when they aren't the same size,
+* then only the whole level will do.
+*/
+pub fn level_changes(old: &GameMatrix, new: &GameMatrix) -> Option<Vec<CellUpdate>> {
+    if old.len() != new.len() || old.first().map(|row| row.len()) != new.first().map(|row| row.len()) {
+        return None;
+    }
+    let mut changes = Vec::new();
+    for (y, (old_row, new_row)) in old.iter().zip(new.iter()).enumerate() {
+        for (x, (old_block, new_block)) in old_row.iter().zip(new_row.iter()).enumerate() {
+            if !old_block.same_look(new_block) {
+                changes.push(CellUpdate { at: Point { x, y }, block: new_block.clone() });
+            }
+        }
+    }
+    Some(changes)
+}
+
+/// Puts the changed cells into the level, leaving out any that don't fit
+pub fn apply_changes(level: &mut GameMatrix, changes: &[CellUpdate]) {
+    for change in changes {
+        if let Some(cell) = level.get_mut(change.at.y).and_then(|row| row.get_mut(change.at.x)) {
+            *cell = change.block.clone();
+        }
+    }
+}
+
+/**
+* Everything the master tells the ui and the renderer. Both get the events over a channel of
+* their own and pick out the ones they need.
+*/
+#[derive(Clone)]
+pub enum GameEvent {
+    StateChanged(GameState),
+    /// The whole level, sent the first time and whenever its size changes
+    Board(GameMatrix),
+    /// The cells that changed since the last board, instead of all of them
+    BoardUpdate(Vec<CellUpdate>),
+    /// The active piece locked in place
+    PieceLocked,
+    /// This many completed rows were just cleared
+    LinesCleared(usize),
+    Score(usize),
+    Hud(HudInfo),
+    /// The next pieces, first one up first
+    Preview(Vec<Piece>),
+    /// The game ended, with how it went
+    GameOver(GameResult),
+    /// The renderer should draw everything again, the old level may still be on the screen
+    Refresh,
+    /// Something the player might want to know that isn't part of the game
+    Info(&'static str),
+}
+
+/**
+* Everything the ui tells the master and the renderer. The master takes the commands and the
+* moves, the renderer what is drawn next to or instead of the level.
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub enum UiEvent {
+    Command(UiCommand),
+    Move(PlayerMove),
+    /// A held key moving the piece, this many steps at once. Not counted as inputs.
+    AutoShift(PlayerMove, usize),
+    /// The lines next to the level: score, preview..
+    Hud(Vec<GameBlock>),
+    /// A full screen (menu, table..) to draw instead of the level
+    Screen(Vec<GameBlock>),
+    /// Lines drawn inside the masked well while paused
+    Overlay(Vec<GameBlock>),
+    CloseScreen,
+    /// The terminal changed, everything is drawn again
+    Redraw,
+}
+
+/// Runs the game, taking in the ui's events and sending game events to the ui and the renderer
+pub trait Controller {
+    fn process(&mut self);
+    fn give_ui_broadcaster(&mut self, broadcaster: GameBroadcaster<GameEvent>);
+    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<GameEvent>);
+    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>);
+}
+
+/// Draws what the master and the ui send it, render takes in everything pending at once
+pub trait Renderer {
+    fn render(&mut self) -> bool;
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>);
+    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>);
+}
+
+/// Turns the player's input into ui events, and game events into what the player sees
+pub trait UIHandler {
+    fn handle_ui(&mut self) -> bool;
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>);
+    fn give_master_broadcaster(&mut self, broadcaster: GameBroadcaster<UiEvent>);
+    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<UiEvent>);
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::game::protocol::CellUpdate;
+use crate::game::tootris::{BlockColor, GameBlock, GameMatrix, Point};
+use crate::settings::{OPTION_BOARD_MAX, OPTION_BOARD_MIN};
+
+static REPLAY_HEADER: &str = "tootris replay 1";
+
+/**
+* One byte per cell, see encode_block
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReplayFrame {
+    /// Game time at which the frame was shown
+    pub time_ms: u64,
+    pub cells: Vec<u8>,
+}
+
+/**
+* Recording of what the level looked like over the course of a game, one frame per change.
+* Frames are stored as bytes rather than GameBlocks to keep long games small.
+*/
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Replay {
+    pub width: usize,
+    pub height: usize,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn new(width: usize, height: usize) -> Self {
+        Replay {
+            width,
+            height,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.frames.last().map_or(0, |frame| frame.time_ms)
+    }
+
+    /// Adds a frame of the level with the piece on top, unless it looks the same as the last one
+    pub fn record(&mut self, time_ms: u64, level: &GameMatrix, piece: &[CellUpdate]) {
+        let mut cells: Vec<u8> = level.iter().flat_map(|row| row.iter().map(encode_block)).collect();
+        for cell in piece {
+            cells[cell.at.y * self.width + cell.at.x] = encode_block(&cell.block);
+        }
+        if self.frames.last().map(|last| &last.cells) == Some(&cells) {
+            return;
+        }
+        self.frames.push(ReplayFrame { time_ms, cells });
+    }
+
+    /// A header line, the size, then one line per frame: the time and a digit per cell
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n{} {}\n", REPLAY_HEADER, self.width, self.height);
+        for frame in self.frames.iter() {
+            let cells: String = frame.cells.iter().map(|cell| (b'0' + cell) as char).collect();
+            text.push_str(&format!("{} {}\n", frame.time_ms, cells));
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(REPLAY_HEADER) {
+            return Err(String::from("not a tootris replay"));
+        }
+        let size: Vec<usize> = lines.next()
+            .map(|(_, line)| line.split_whitespace().filter_map(|n| n.parse().ok()).collect())
+            .unwrap_or_default();
+        if size.len() != 2 || size[0] == 0 || size[1] == 0 {
+            return Err(String::from("line 2: expected the width and height of the level"));
+        }
+        // playback goes through the same bitboard as a game, so the level can't be larger than a game's
+        if !(OPTION_BOARD_MIN.x..=OPTION_BOARD_MAX.x).contains(&size[0])
+            || !(OPTION_BOARD_MIN.y..=OPTION_BOARD_MAX.y).contains(&size[1]) {
+            return Err(format!("line 2: the level must be from {}x{} to {}x{}, not {}x{}",
+                               OPTION_BOARD_MIN.x, OPTION_BOARD_MIN.y, OPTION_BOARD_MAX.x, OPTION_BOARD_MAX.y,
+                               size[0], size[1]));
+        }
+        let mut replay = Replay::new(size[0], size[1]);
+        for (number, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let bad_frame = || format!("line {}: expected the time and {} cells", number + 1,
+                                       replay.width * replay.height);
+            let (time, cells) = line.trim().split_once(' ').ok_or_else(bad_frame)?;
+            let time_ms: u64 = time.parse().map_err(|_| bad_frame())?;
+            let cells: Vec<u8> = cells.bytes()
+                .map(|c| if c.is_ascii_digit() { Some(c - b'0') } else { None })
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(bad_frame)?;
+            if cells.len() != replay.width * replay.height || time_ms < replay.duration_ms() {
+                return Err(bad_frame());
+            }
+            replay.frames.push(ReplayFrame { time_ms, cells });
+        }
+        if replay.is_empty() {
+            return Err(String::from("the replay has no frames"));
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The cells that differ between two frames
+    pub fn changes(&self, from: usize, to: usize) -> Vec<CellUpdate> {
+        self.frames[from].cells.iter().zip(self.frames[to].cells.iter()).enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(i, (_, new))| CellUpdate {
+                at: Point { x: i % self.width, y: i / self.width },
+                block: decode_block(*new),
+            })
+            .collect()
+    }
+
+    pub fn level_at(&self, frame: usize) -> GameMatrix {
+        self.frames[frame].cells
+            .chunks(self.width)
+            .map(|row| row.iter().map(|cell| decode_block(*cell)).collect())
+            .collect()
+    }
+}
+
+static COLORS: [BlockColor; 6] = [BlockColor::Blue, BlockColor::Magenta, BlockColor::Yellow,
+    BlockColor::Green, BlockColor::Cyan, BlockColor::White];
+
+fn encode_block(block: &GameBlock) -> u8 {
+    match block {
+        GameBlock::Empty => 0,
+        GameBlock::Indestructible => 1,
+        GameBlock::Filled(color) => 2 + COLORS.iter().position(|c| c == color).unwrap_or(0) as u8,
+        //text only shows up outside of play, nothing worth keeping
+        _ => 0,
+    }
+}
+
+fn decode_block(cell: u8) -> GameBlock {
+    match cell {
+        0 => GameBlock::Empty,
+        1 => GameBlock::Indestructible,
+        n => GameBlock::Filled(COLORS[(n as usize - 2) % COLORS.len()]),
+    }
+}
@@ -0,0 +1,85 @@
+use crate::game::piece_types::PLAYABLE_PIECES;
+use crate::game::tootris::{GameBlock, GameMatrix};
+
+/**
+* Running statistics of one game, updated by the controller as pieces lock and lines clear
+*/
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct GameStats {
+    pub pieces: usize,
+    /// Same order as PLAYABLE_PIECES
+    pub piece_counts: [usize; 7],
+    /// Number of clears of 1, 2, 3 and 4 (or more) lines
+    pub clears: [usize; 4],
+    pub inputs: usize,
+    pub max_height: usize,
+    /// Holes in the stack, sampled every time a piece locks
+    pub holes: Vec<usize>,
+}
+
+impl GameStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// holes as counted by Occupancy::holes, so there is only the one definition of a hole
+    pub fn piece_placed(&mut self, kind: Option<&str>, level: &GameMatrix, holes: usize) {
+        self.pieces += 1;
+        if let Some(kind) = kind {
+            if let Some(i) = PLAYABLE_PIECES.iter().position(|playable| *playable == kind) {
+                self.piece_counts[i] += 1;
+            }
+        }
+        let height = stack_height(level);
+        if height > self.max_height {
+            self.max_height = height;
+        }
+        self.holes.push(holes);
+    }
+
+    pub fn input(&mut self) {
+        self.inputs += 1;
+    }
+
+    pub fn lines_cleared(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.clears[lines.min(4) - 1] += 1;
+    }
+
+    pub fn pieces_per_second(&self, duration_ms: u64) -> f64 {
+        if duration_ms == 0 {
+            return 0.0;
+        }
+        self.pieces as f64 * 1000.0 / duration_ms as f64
+    }
+
+    pub fn inputs_per_piece(&self) -> f64 {
+        if self.pieces == 0 {
+            return 0.0;
+        }
+        self.inputs as f64 / self.pieces as f64
+    }
+
+    pub fn current_holes(&self) -> usize {
+        *self.holes.last().unwrap_or(&0)
+    }
+
+    pub fn max_holes(&self) -> usize {
+        *self.holes.iter().max().unwrap_or(&0)
+    }
+}
+
+/**
+* Rows from the floor up to and including the highest filled block. Border blocks don't count.
+*/
+pub fn stack_height(level: &GameMatrix) -> usize {
+    for (y, row) in level.iter().enumerate() {
+        if row.iter().any(|block| matches!(block, GameBlock::Filled(_))) {
+            //the last row is the floor
+            return level.len() - 1 - y;
+        }
+    }
+    0
+}
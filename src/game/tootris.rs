@@ -1,9 +1,12 @@
-use std::sync::mpsc::Sender;
-use std::sync::mpsc::Receiver;
 use std::fmt;
 use std::fmt::{Formatter, Display};
+use crate::game::stats::GameStats;
+use crate::settings::{OPTION_SPRINT_LINES, OPTION_ULTRA_TIME_MS, OPTION_DAS_MS, OPTION_ARR_MS,
+                      OPTION_SOFT_DROP_FACTOR, OPTION_GHOST, OPTION_PREVIEW, OPTION_BOARD_WIDTH,
+                      OPTION_BOARD_HEIGHT, OPTION_TICK_BASE_MS, OPTION_BUCKET_MAX_SIZE, OPTION_COUNTDOWN_MS,
+                      OPTION_LOCK_DELAY_FRAMES, OPTION_ARE_FRAMES, OPTION_LINE_CLEAR_FRAMES};
 
-pub(crate) type GameMatrix = Vec<Vec<GameBlock>>;
+pub type GameMatrix = Vec<Vec<GameBlock>>;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum BlockColor {
@@ -20,6 +23,8 @@ pub enum GameBlock {
     Filled(BlockColor),
     Empty,
     Indestructible,
+    /// Where the active piece would land
+    Ghost(BlockColor),
     String(String, BlockColor),
     None,
 }
@@ -31,6 +36,16 @@ impl PartialEq for GameBlock {
 }
 
 impl GameBlock {
+    /// == only compares the kind of block, this also compares the color and the text
+    pub fn same_look(&self, other: &Self) -> bool {
+        match (self, other) {
+            (GameBlock::Filled(a), GameBlock::Filled(b))
+            | (GameBlock::Ghost(a), GameBlock::Ghost(b)) => a == b,
+            (GameBlock::String(a, a_color), GameBlock::String(b, b_color)) => a == b && a_color == b_color,
+            _ => self == other,
+        }
+    }
+
     pub fn get_color(&self) -> Option<&BlockColor> {
         match self {
             GameBlock::Filled(val) => Some(&val),
@@ -110,22 +125,32 @@ pub enum UiCommand {
     Pause,
     Resume,
     Exit,
-    RenderOffset(Point),
-    RefreshUi,
-}
-
-#[derive(Clone, PartialEq, Debug)]
-pub enum ControllerCommand {
-    FullRefresh,
+    /// Width and height of the level, including the border
+    BoardSize(Point),
+    Randomizer(Randomizer),
+    /// Start a new game in the given mode
+    Start(GameMode),
+    /// Back to the start menu, throwing away the current game
+    Menu,
+    /// Play back the last game, only available once it has ended
+    Replay,
+    StopReplay,
+    /// Soft drop was pressed (true) or let go (false)
+    SoftDrop(bool),
+    Handling(Handling),
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum GameState {
     Paused,
+    /// Counting down before play starts or resumes, only rotation is accepted
+    Countdown(Countdown),
     Playing,
     Start,
     Tootris,
     End,
+    /// Showing the recording of the game that just ended
+    Replay,
     Exit,
 }
 
@@ -133,114 +158,264 @@ impl Display for GameState {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             GameState::Paused => write!(f, "Paused"),
+            GameState::Countdown(phase) => write!(f, "Countdown({})", phase),
             GameState::Playing => write!(f, "Playing"),
             GameState::Start => write!(f, "Start"),
             GameState::Tootris => write!(f, "Tootris"),
             GameState::End => write!(f, "End"),
+            GameState::Replay => write!(f, "Replay"),
             GameState::Exit => write!(f, "Exit"),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Point {
-    pub x: usize,
-    pub y: usize,
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Countdown {
+    Ready,
+    Go,
 }
 
-impl Display for Point {
+impl Display for Countdown {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "({},{})", self.x, self.y)
+        match self {
+            Countdown::Ready => write!(f, "READY"),
+            Countdown::Go => write!(f, "GO"),
+        }
     }
 }
 
-impl PartialEq for Point {
-    fn eq(&self, other: &Self) -> bool {
-        self.x == other.x && self.y == other.y
-    }
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameMode {
+    /// Endless, play until topping out
+    Marathon,
+    /// Clear a set number of lines as fast as possible
+    Sprint,
+    /// Score as much as possible within a time limit
+    Ultra,
 }
 
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Ultra];
 
-#[derive(Clone, Copy, PartialEq)]
-pub enum PlayerMove {
-    StepRight,
-    StepLeft,
-    StepDown,
-    RotateForward,
+    /// Key for the mode in files such as the high score table
+    pub fn key(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "marathon",
+            GameMode::Sprint => "sprint",
+            GameMode::Ultra => "ultra",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().find(|mode| mode.key() == key).copied()
+    }
+
+    /// The game is won once this many lines are cleared
+    pub fn goal_lines(&self) -> Option<usize> {
+        match self {
+            GameMode::Sprint => Some(OPTION_SPRINT_LINES),
+            _ => None,
+        }
+    }
+
+    /// The game ends when this much time has been played
+    pub fn time_limit_ms(&self) -> Option<u64> {
+        match self {
+            GameMode::Ultra => Some(OPTION_ULTRA_TIME_MS),
+            _ => None,
+        }
+    }
+
+    /// Sprint is about speed, everything else about score
+    pub fn ranks_by_time(&self) -> bool {
+        self.goal_lines().is_some()
+    }
+}
+
+impl Display for GameMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GameMode::Marathon => write!(f, "Marathon"),
+            GameMode::Sprint => write!(f, "Sprint"),
+            GameMode::Ultra => write!(f, "Ultra"),
+        }
+    }
 }
 
 /**
-* Wrappers for communication between the components (UI, Renderer, controller..)
+* How the next pieces are picked
 */
-#[derive(Clone)]
-pub struct GameBroadcaster<T> {
-    pub channel_out: Sender<T>,
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Randomizer {
+    /// Every piece is an independent roll
+    Random,
+    /// Pieces are dealt from shuffled bags holding one of each
+    Bag,
 }
 
-pub struct GameUpdateReceiver<T> {
-    pub receiver: Receiver<T>,
+impl Display for Randomizer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Randomizer::Random => write!(f, "Random"),
+            Randomizer::Bag => write!(f, "7-bag"),
+        }
+    }
 }
 
-pub struct Master2RenderCommunique {
-    pub comm_type: Communique,
-    pub level: Option<GameMatrix>,
-    pub state: Option<GameState>,
-    pub score: Option<usize>,
-    pub command: Option<ControllerCommand>,
+/**
+* How the game feels, changed from the settings menu
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Handling {
+    pub das_ms: u64,
+    /// 0 moves straight to the wall
+    pub arr_ms: u64,
+    /// 0 is sonic drop
+    pub soft_drop_factor: usize,
+    pub ghost: bool,
+    pub preview: usize,
 }
 
-pub struct Master2UICommunique {
-    pub comm_type: Communique,
-    pub state: Option<GameState>,
-    pub score: Option<usize>,
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das_ms: OPTION_DAS_MS,
+            arr_ms: OPTION_ARR_MS,
+            soft_drop_factor: OPTION_SOFT_DROP_FACTOR,
+            ghost: OPTION_GHOST,
+            preview: OPTION_PREVIEW,
+        }
+    }
 }
 
-pub struct UI2MasterCommunique {
-    pub comm_type: Communique,
-    pub command: Option<UiCommand>,
-    pub player_move: Option<PlayerMove>,
+/**
+* Tuning of the game itself, read from the [game] section of the config file
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GameConfig {
+    /// Width and height of the level, including the border
+    pub width: usize,
+    pub height: usize,
+    pub mode: GameMode,
+    /// Gravity tick at speed 1 is 99 times this
+    pub tick_base_ms: usize,
+    /// How many pieces are drawn ahead of time
+    pub bucket_size: usize,
+    /// Deal the same pieces in every game, random if None
+    pub seed: Option<u64>,
+    /// Length of the READY/GO countdown in ms, 0 starts play right away
+    pub countdown_ms: u64,
+    pub lock_delay_frames: u64,
+    /// Entry delay between a piece locking and the next one showing up
+    pub are_frames: u64,
+    pub line_clear_frames: u64,
 }
 
-impl UI2MasterCommunique {
-    pub fn is_player_move(&self) -> bool {
-        self.player_move.is_some()
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            width: OPTION_BOARD_WIDTH,
+            height: OPTION_BOARD_HEIGHT,
+            mode: GameMode::Marathon,
+            tick_base_ms: OPTION_TICK_BASE_MS,
+            bucket_size: OPTION_BUCKET_MAX_SIZE,
+            seed: None,
+            countdown_ms: OPTION_COUNTDOWN_MS,
+            lock_delay_frames: OPTION_LOCK_DELAY_FRAMES,
+            are_frames: OPTION_ARE_FRAMES,
+            line_clear_frames: OPTION_LINE_CLEAR_FRAMES,
+        }
     }
+}
+
+/**
+* Final numbers of a finished game
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameResult {
+    pub mode: GameMode,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub duration_ms: u64,
+    pub stats: GameStats,
+}
 
-    pub fn is_command(&self) -> bool {
-        self.command.is_some()
+impl GameResult {
+    /// false if the goal of the mode was not reached, e.g. topping out during a sprint
+    pub fn completed(&self) -> bool {
+        match self.mode.goal_lines() {
+            Some(goal) => self.lines >= goal,
+            None => true,
+        }
     }
 }
 
-pub struct UI2RenderCommunique {
-    pub com_type: Communique,
-    pub vector: Option<Vec<GameBlock>>,
-    pub command: Option<UiCommand>,
+/**
+* Live numbers for the side panel, sent to the ui continuously while playing
+*/
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct HudInfo {
+    pub elapsed_ms: u64,
+    pub lines: usize,
+    pub level: usize,
+    pub pieces: usize,
+    pub inputs: usize,
 }
 
+impl HudInfo {
+    pub fn pieces_per_second(&self) -> f64 {
+        if self.elapsed_ms == 0 {
+            return 0.0;
+        }
+        self.pieces as f64 * 1000.0 / self.elapsed_ms as f64
+    }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Communique {
-    Update,
-    Info(&'static str),
-    Error(&'static str),
+    pub fn actions_per_minute(&self) -> f64 {
+        if self.elapsed_ms == 0 {
+            return 0.0;
+        }
+        self.inputs as f64 * 60000.0 / self.elapsed_ms as f64
+    }
 }
 
-pub trait Controller {
-    fn process(&mut self);
-    fn give_ui_broadcaster(&mut self, broadcaster: GameBroadcaster<Master2UICommunique>);
-    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<Master2RenderCommunique>);
-    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UI2MasterCommunique>);
+#[derive(Clone, Copy, Debug)]
+pub struct Point {
+    pub x: usize,
+    pub y: usize,
 }
 
-pub trait Renderer {
-    fn render(&mut self) -> bool;
-    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<Master2RenderCommunique>);
-    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UI2RenderCommunique>);
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
 }
 
-pub trait UIHandler {
-    fn handle_ui(&mut self) -> bool;
-    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<Master2UICommunique>);
-    fn give_master_broadcaster(&mut self, broadcaster: GameBroadcaster<UI2MasterCommunique>);
-    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<UI2RenderCommunique>);
+impl PartialEq for Point {
+    fn eq(&self, other: &Self) -> bool {
+        self.x == other.x && self.y == other.y
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlayerMove {
+    StepRight,
+    StepLeft,
+    StepDown,
+    RotateForward,
+    /// Turn the piece to a given orientation, regardless of how it is turned now
+    OrientUp,
+    OrientDown,
+    OrientLeft,
+    OrientRight,
+    /// Straight to the floor, locking the piece at once
+    HardDrop,
+}
+
+impl PlayerMove {
+    pub fn is_rotation(&self) -> bool {
+        matches!(self, PlayerMove::RotateForward | PlayerMove::OrientUp | PlayerMove::OrientDown
+            | PlayerMove::OrientLeft | PlayerMove::OrientRight)
+    }
 }
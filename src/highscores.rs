@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::game::tootris::{GameMode, GameResult};
+use crate::settings::{HIGH_SCORE_FILE, OPTION_HIGH_SCORE_ENTRIES, OPTION_NAME_MAX_LENGTH};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub duration_ms: u64,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl HighScoreEntry {
+    pub fn from_result(name: &str, result: &GameResult) -> Self {
+        HighScoreEntry {
+            name: sanitize_name(name),
+            score: result.score,
+            lines: result.lines,
+            level: result.level,
+            duration_ms: result.duration_ms,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs()).unwrap_or(0),
+        }
+    }
+
+    /// true if self belongs above other in the table of the given mode
+    fn beats(&self, other: &Self, mode: GameMode) -> bool {
+        if mode.ranks_by_time() && self.duration_ms != other.duration_ms {
+            return self.duration_ms < other.duration_ms;
+        }
+        if self.score != other.score {
+            return self.score > other.score;
+        }
+        self.duration_ms < other.duration_ms
+    }
+
+    pub fn date(&self) -> String {
+        format_date(self.timestamp)
+    }
+
+    pub fn duration(&self) -> String {
+        format_duration(self.duration_ms)
+    }
+
+    /// A row of the high score table, see table_header
+    pub fn table_row(&self, rank: usize) -> String {
+        format!("{:>2}. {:<width$} {:>7} {:>6} {:>4} {:>8}  {:<10}",
+                rank, self.name, self.score, self.lines, self.level, self.duration(), self.date(),
+                width = OPTION_NAME_MAX_LENGTH)
+    }
+
+    fn to_line(&self, mode: &str) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}", mode, self.score, self.lines, self.level,
+                self.duration_ms, self.timestamp, self.name)
+    }
+
+    fn from_line(line: &str) -> Option<(GameMode, Self)> {
+        let fields: Vec<&str> = line.splitn(7, '\t').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        Some((GameMode::from_key(fields[0])?, HighScoreEntry {
+            score: fields[1].parse().ok()?,
+            lines: fields[2].parse().ok()?,
+            level: fields[3].parse().ok()?,
+            duration_ms: fields[4].parse().ok()?,
+            timestamp: fields[5].parse().ok()?,
+            name: fields[6].to_string(),
+        }))
+    }
+}
+
+/**
+* The top results for each game mode, kept in a tab separated file in the user's data directory.
+* A store without a path works fine, it just forgets everything when dropped.
+*/
+pub struct HighScoreStore {
+    path: Option<PathBuf>,
+    tables: HashMap<&'static str, Vec<HighScoreEntry>>,
+    max_entries: usize,
+}
+
+impl HighScoreStore {
+    pub fn new(path: Option<PathBuf>, max_entries: usize) -> Self {
+        HighScoreStore {
+            path,
+            tables: HashMap::new(),
+            max_entries,
+        }
+    }
+
+    /**
+    * Loads the store from the default location. A missing or unreadable file gives an empty store,
+    * the read error comes along so the caller can show it wherever it has room.
+    */
+    pub fn load_default() -> (Self, io::Result<()>) {
+        let mut store = Self::new(default_path(), OPTION_HIGH_SCORE_ENTRIES);
+        let loaded = store.load();
+        (store, loaded)
+    }
+
+    pub fn load(&mut self) -> io::Result<()> {
+        self.tables.clear();
+        let contents = match self.path.as_ref() {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e),
+            },
+            None => return Ok(()),
+        };
+        for line in contents.lines() {
+            //skip anything mangled instead of throwing away the whole table
+            if let Some((mode, entry)) = HighScoreEntry::from_line(line) {
+                self.insert(mode, entry);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = String::new();
+        for mode in GameMode::ALL.iter() {
+            for entry in self.table(*mode) {
+                contents.push_str(&entry.to_line(mode.key()));
+                contents.push('\n');
+            }
+        }
+        fs::write(path, contents)
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScoreEntry] {
+        match self.tables.get(mode.key()) {
+            Some(table) => table.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Position (0 based) the result would get in the table, or None if it doesn't make the cut
+    pub fn rank_of(&self, result: &GameResult) -> Option<usize> {
+        if !result.completed() || (result.score == 0 && !result.mode.ranks_by_time()) {
+            return None;
+        }
+        let candidate = HighScoreEntry::from_result("", result);
+        let table = self.table(result.mode);
+        let rank = table.iter().position(|entry| candidate.beats(entry, result.mode))
+            .unwrap_or(table.len());
+        if rank < self.max_entries {
+            return Some(rank);
+        }
+        None
+    }
+
+    pub fn qualifies(&self, result: &GameResult) -> bool {
+        self.rank_of(result).is_some()
+    }
+
+    /// Inserts the entry in order and returns its rank, or None if it fell off the table
+    pub fn insert(&mut self, mode: GameMode, entry: HighScoreEntry) -> Option<usize> {
+        let table = self.tables.entry(mode.key()).or_default();
+        let rank = table.iter().position(|other| entry.beats(other, mode)).unwrap_or(table.len());
+        if rank >= self.max_entries {
+            return None;
+        }
+        table.insert(rank, entry);
+        table.truncate(self.max_entries);
+        Some(rank)
+    }
+}
+
+pub fn table_header() -> String {
+    format!(" #  {:<width$} {:>7} {:>6} {:>4} {:>8}  {:<10}",
+            "NAME", "SCORE", "LINES", "LVL", "TIME", "DATE", width = OPTION_NAME_MAX_LENGTH)
+}
+
+/// $XDG_DATA_HOME/tootris, falling back to ~/.local/share/tootris (or %APPDATA%\tootris)
+pub fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("tootris"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(HIGH_SCORE_FILE))
+}
+
+/// Names end up in a tab separated file, so keep them printable and short
+pub fn sanitize_name(name: &str) -> String {
+    let clean: String = name.chars()
+        .filter(|c| !c.is_control())
+        .take(OPTION_NAME_MAX_LENGTH)
+        .collect();
+    let clean = clean.trim();
+    if clean.is_empty() {
+        return String::from("anonymous");
+    }
+    clean.to_string()
+}
+
+pub fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}.{}", seconds / 60, seconds % 60, (ms % 1000) / 100)
+}
+
+/// yyyy-mm-dd in UTC, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn format_date(timestamp: u64) -> String {
+    let z = (timestamp / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
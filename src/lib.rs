@@ -0,0 +1,15 @@
+/*!
+* The tootris engine: the game master, pieces, levels and the messages it trades with a ui and a
+* renderer. The terminal frontend built on crossterm comes with the `tui` feature, which is on
+* by default. Without it the crate has no terminal dependencies.
+*/
+pub mod game;
+pub mod settings;
+pub mod highscores;
+#[cfg(feature = "tui")]
+pub mod ui;
+#[cfg(feature = "tui")]
+pub mod cli;
+
+#[cfg(all(test, feature = "tui"))]
+mod tests;
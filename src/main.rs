@@ -1,122 +1,270 @@
-use std::io::{stdout};
+use std::io::{stdin, stdout, BufReader, Result};
+use std::thread;
 use std::sync::mpsc::{channel, Receiver, Sender};
 
-use crossterm::{
-    Result,
-};
+use tootris::game::game_loop_controller::EvilGameMaster;
+use tootris::game::protocol::{wakeup_channel, GameBroadcaster, GameEvent, GameUpdateReceiver, UiEvent, Wakeup,
+                              WakeupReceiver};
 
-use game::game_loop_controller::EvilGameMaster;
-use game::tootris::{GameBroadcaster, GameUpdateReceiver,
-                    Master2RenderCommunique, UI2MasterCommunique};
 
-
-use crate::ui::crossterm_render::TermRenderer;
-use crate::game::tootris::{Renderer, Master2UICommunique, UI2RenderCommunique, UIHandler};
-use crate::ui::crossterm_ui::TermUI;
-
-
-mod tests;
-mod settings;
-mod ui;
-mod game;
+use tootris::ui::crossterm_render::TermRenderer;
+use tootris::game::protocol::{Renderer, UIHandler};
+use tootris::ui::crossterm_ui::TermUI;
+use tootris::ui::render_loop::run_renderer;
+use tootris::ui::terminal_guard::TerminalGuard;
+use tootris::ui::text_render::TextRenderer;
+use tootris::ui::text_ui::TextUI;
+use tootris::ui::config::UserConfig;
+use tootris::cli::{Cli, Command, HELP};
+use tootris::game::bot;
+use tootris::game::replay::Replay;
+use tootris::game::tootris::{GameMode, GameState};
+use tootris::highscores::{data_dir, table_header, HighScoreStore};
+use tootris::settings::LAST_REPLAY_FILE;
+use crossterm::event::{poll, read, Event, KeyEventKind};
 
 use std::env;
+use std::path::PathBuf;
+use std::process;
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let height: usize;
-    let width: usize;
-    match args.len() {
-        2 => {
-            height = 24;
-            width = args[1].parse().unwrap()
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = Cli::parse(&args).unwrap_or_else(|e| exit_with(&e));
+    match cli.command {
+        Command::Help => {
+            print!("{}", HELP);
+            return Ok(());
         }
-        3 => {
-            height = args[2].parse().unwrap();
-            width = args[1].parse().unwrap();
+        Command::Scores { mode } => {
+            print_scores(mode);
+            return Ok(());
         }
-        _ => {
-            height = 24;
-            width = 10
+        _ => {}
+    }
+    let (config, config_warnings) = cli.config().unwrap_or_else(|e| exit_with(&e));
+    match cli.command {
+        Command::Replay { file } => watch_replay(&config, file),
+        Command::Bot { games, pieces } => {
+            run_bot(&config, games, pieces);
+            Ok(())
         }
+        Command::Play { text } => play(config, config_warnings, text),
+        _ => Ok(()),
     }
-    let gm_2_render: (Sender<Master2RenderCommunique>,
-                      Receiver<Master2RenderCommunique>) = channel();
+}
+
+fn exit_with(error: &str) -> ! {
+    eprintln!("tootris: {}", error);
+    process::exit(2);
+}
+
+fn play(config: UserConfig, config_warnings: Vec<String>, text_mode: bool) -> Result<()> {
+    //everything sent to the game or the renderer wakes up the thread it runs on
+    let (wakeup, woken) = wakeup_channel();
+    let (render_wakeup, render_woken) = wakeup_channel();
+    let gm_2_render: (Sender<GameEvent>, Receiver<GameEvent>) = channel();
 
     let gm_to_render_receiver = GameUpdateReceiver {
         receiver: gm_2_render.1,
     };
-    let gm_to_render_sender = GameBroadcaster {
-        channel_out: gm_2_render.0,
-    };
+    let gm_to_render_sender = GameBroadcaster::new(gm_2_render.0).with_wakeup(render_wakeup.clone());
 
-    let ui_to_gm: (Sender<UI2MasterCommunique>, Receiver<UI2MasterCommunique>) = channel();
+    let ui_to_gm: (Sender<UiEvent>, Receiver<UiEvent>) = channel();
 
     let ui_to_gm_receiver = GameUpdateReceiver {
         receiver: ui_to_gm.1,
     };
-    let ui_to_gm_sender = GameBroadcaster {
-        channel_out: ui_to_gm.0,
-    };
-    let gm_to_ui: (Sender<Master2UICommunique>, Receiver<Master2UICommunique>) = channel();
-    let master_to_ui_sender = GameBroadcaster {
-        channel_out: gm_to_ui.0,
-    };
+    let ui_to_gm_sender = GameBroadcaster::new(ui_to_gm.0).with_wakeup(wakeup.clone());
+    let gm_to_ui: (Sender<GameEvent>, Receiver<GameEvent>) = channel();
+    let master_to_ui_sender = GameBroadcaster::new(gm_to_ui.0);
 
     let master_to_ui_receiver = GameUpdateReceiver {
         receiver: gm_to_ui.1,
     };
 
-    let ui_to_render: (Sender<UI2RenderCommunique>, Receiver<UI2RenderCommunique>) = channel();
+    let ui_to_render: (Sender<UiEvent>, Receiver<UiEvent>) = channel();
 
-    let ui_to_render_sender = GameBroadcaster {
-        channel_out: ui_to_render.0,
-    };
+    let ui_to_render_sender = GameBroadcaster::new(ui_to_render.0).with_wakeup(render_wakeup);
 
     let ui_to_render_receiver = GameUpdateReceiver {
         receiver: ui_to_render.1,
     };
 
+    if text_mode {
+        for warning in config_warnings.iter() {
+            eprintln!("tootris: {}", warning);
+        }
+        let ui = TextUI::new(Some(ui_to_gm_sender),
+                             Some(master_to_ui_receiver),
+                             Box::new(BufReader::new(stdin())));
+        let mut master = EvilGameMaster::new(&config.game, None,
+                                             Some(gm_to_render_sender),
+                                             Some(master_to_ui_sender),
+                                             Some(ui_to_gm_receiver));
+        master.replay_file = last_replay_path();
+        //moves come in whole lines, a countdown would only eat the first ones
+        master.countdown_ms = 0;
+        let renderer = TextRenderer::new(Some(gm_to_render_receiver),
+                                         Some(ui_to_render_receiver),
+                                         Some(stdout()));
+        let threads = Threads { wakeup, woken, render_woken, max_fps: config.display.max_fps };
+        run(master, ui, renderer, threads, false);
+        return Ok(());
+    }
+
+    let display = config.display;
+    let game_config = config.game;
     let mut ui = TermUI::new(Some(ui_to_gm_sender),
                              Some(ui_to_render_sender),
-                             Some(master_to_ui_receiver));
-
-    let mut master = EvilGameMaster::new(height, width, None,
+                             Some(master_to_ui_receiver),
+                             config, config_warnings);
+    let master_to_ui_sender = master_to_ui_sender.with_wakeup(ui.wakeup());
+    let mut master = EvilGameMaster::new(&game_config, None,
                                          Some(gm_to_render_sender),
                                          Some(master_to_ui_sender),
                                          Some(ui_to_gm_receiver));
+    master.replay_file = last_replay_path();
 
-    let mut my_renderer = TermRenderer {
-        from_master: Some(gm_to_render_receiver),
-        from_ui: Some(ui_to_render_receiver),
-        out: Some(stdout()),
-        current_matrix: Some(master.level.clone()),
-        draw_buffer: None,
-        term_size: None,
-        state: None,
-        render_offset: None,
-        ui_vector: None,
-    };
-    master.new_game();
-    master.resume_game();
+    let mut my_renderer = TermRenderer::new(Some(gm_to_render_receiver),
+                                            Some(ui_to_render_receiver),
+                                            Some(stdout()),
+                                            Some(master.level.clone()),
+                                            display);
+
+    let terminal = TerminalGuard::new()?;
+    ui.set_key_releases(terminal.reports_releases());
     my_renderer.full_refresh();
-    use std::thread;
+    ui.open_start_menu();
+    let threads = Threads { wakeup, woken, render_woken, max_fps: display.max_fps };
+    run(master, ui, my_renderer, threads, true);
+    Ok(())
+}
+
+fn last_replay_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(LAST_REPLAY_FILE))
+}
+
+/// Plays a recording back in the terminal, any key stops it
+fn watch_replay(config: &UserConfig, file: Option<PathBuf>) -> Result<()> {
+    let path = file.or_else(last_replay_path)
+        .unwrap_or_else(|| exit_with("no data directory to find the last replay in"));
+    if !path.is_file() {
+        exit_with(&format!("no replay at {}, finish a game first or give a replay file", path.display()));
+    }
+    let replay = Replay::load(&path).unwrap_or_else(|e| exit_with(&e));
 
+    let (render_sender, render_receiver) = channel();
+    let mut master = EvilGameMaster::new(&config.game, None,
+                                         Some(GameBroadcaster::new(render_sender)),
+                                         None, None);
+    master.play_recording(replay);
+    let mut renderer = TermRenderer::new(Some(GameUpdateReceiver { receiver: render_receiver }),
+                                         None,
+                                         Some(stdout()),
+                                         Some(master.level.clone()),
+                                         config.display);
+    let _terminal = TerminalGuard::new()?;
+    renderer.full_refresh();
+    while master.state == GameState::Replay {
+        master.process_game();
+        renderer.render();
+        //waits for a key until the next frame is due
+        if poll(master.next_frame_in().unwrap_or_default())? {
+            //the release of the key that started the replay doesn't stop it
+            if let Event::Key(key) = read()? {
+                if key.kind != KeyEventKind::Release {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_scores(mode: Option<GameMode>) {
+    let (store, loaded) = HighScoreStore::load_default();
+    if let Err(e) = loaded {
+        eprintln!("tootris: could not read high score file: {}", e);
+    }
+    let modes: Vec<GameMode> = mode.map_or(GameMode::ALL.to_vec(), |mode| vec![mode]);
+    for (i, mode) in modes.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("HIGH SCORES - {}", mode);
+        println!("{}", table_header());
+        let table = store.table(*mode);
+        if table.is_empty() {
+            println!(" no scores yet");
+        }
+        for (rank, entry) in table.iter().enumerate() {
+            println!("{}", entry.table_row(rank + 1));
+        }
+    }
+}
+
+/// Lets the bot play and prints how every game went
+fn run_bot(config: &UserConfig, games: usize, pieces: usize) {
+    let mut game_config = config.game;
+    let mut total_score = 0;
+    let mut total_lines = 0;
+    for game in 0..games {
+        //a seed gives a different but repeatable game each round
+        game_config.seed = config.game.seed.map(|seed| seed.wrapping_add(game as u64));
+        let mut master = EvilGameMaster::new(&game_config, None, None, None, None);
+        let result = bot::play(&mut master, pieces);
+        total_score += result.score;
+        total_lines += result.lines;
+        println!("game {}: {} score {}, lines {}, pieces {}, max height {}, holes {}{}",
+                 game + 1, result.mode, result.score, result.lines, result.stats.pieces,
+                 result.stats.max_height, result.stats.current_holes(),
+                 if master.state == GameState::End { "" } else { ", stopped at the piece limit" });
+    }
+    if games > 1 {
+        println!("average over {} games: score {:.1}, lines {:.1}", games,
+                 total_score as f64 / games as f64, total_lines as f64 / games as f64);
+    }
+}
+
+/// How the game thread and the render thread wake up
+struct Threads {
+    /// Rings the game thread, for when the renderer quits
+    wakeup: Wakeup,
+    woken: WakeupReceiver,
+    render_woken: WakeupReceiver,
+    max_fps: usize,
+}
+
+/// Runs the ui and the renderer on threads of their own and the game on this one until one of them quits.
+/// In between, this thread sleeps until something is sent to the game or the next frame of the game is
+/// due, the renderer sleeps until something is sent to it.
+/// A ui blocked on reading its input can't always be joined, wait_for_ui says whether to wait for it.
+fn run<U: UIHandler + Send + 'static, R: Renderer + Send + 'static>(mut master: EvilGameMaster, mut ui: U,
+                                                                    renderer: R, threads: Threads,
+                                                                    wait_for_ui: bool) {
     let handler = thread::spawn(move || {
         let mut run = true;
         while run {
             run = ui.handle_ui();
         }
     });
-    
-
-    let mut run = true;
-    while run {
-        if !run {break;}
-        run = master.process_game();
-        if !run {break;}
-        run = my_renderer.render();
+    let Threads { wakeup, woken, render_woken, max_fps } = threads;
+    let render_thread = thread::spawn(move || {
+        run_renderer(renderer, render_woken, max_fps);
+        wakeup.ring();
+    });
+
+    while !render_thread.is_finished() {
+        if !master.process_game() {
+            break;
+        }
+        woken.wait(master.next_frame_in());
     }
-    handler.join();
-    Ok(())
-}
\ No newline at end of file
+    //the renderer quits on the exit the game sent when it stopped
+    if render_thread.join().is_err() {
+        eprintln!("Render thread panicked");
+    }
+    if wait_for_ui && handler.join().is_err() {
+        eprintln!("UI thread panicked");
+    }
+}
@@ -1,25 +1,80 @@
 use crate::game::tootris::Point;
 
-pub(crate) static OPTION_TICK_BASE_MS: usize = 4;
+//Defaults for what the config file leaves out, see GameConfig and DisplayConfig
+pub static OPTION_TICK_BASE_MS: usize = 4;
+/// The game runs in fixed steps of this many per second, whatever the rendering does
+pub static OPTION_FRAME_RATE: u64 = 60;
+/// A game that falls behind catches up at most this many frames at once and slows down
+/// instead, e.g. after the computer was suspended
+pub static OPTION_MAX_CATCH_UP_FRAMES: u64 = 15;
+/// Frames a piece can rest on the stack before it locks
+pub static OPTION_LOCK_DELAY_FRAMES: u64 = 30;
+/// Moving or rotating a resting piece starts its lock delay over, this many times per piece
+pub static OPTION_LOCK_RESETS: usize = 15;
+/// Entry delay, frames between a piece locking and the next one showing up
+pub static OPTION_ARE_FRAMES: u64 = 6;
+/// Frames completed rows stay on screen before they are cleared
+pub static OPTION_LINE_CLEAR_FRAMES: u64 = 20;
 //todo: tweak
-pub(crate) static OPTION_BUCKET_MAX_SIZE: usize = 20;
-pub(crate) static OPTION_BUCKET_MINIMUM_SIZE: usize = 3;
-pub(crate) static XRENDER_OFFSET: usize = 20;
-pub(crate) static UI_ANCHOR: Point = Point { x: 0, y: 0 };
+pub static OPTION_BUCKET_MAX_SIZE: usize = 20;
+pub static OPTION_BUCKET_MINIMUM_SIZE: usize = 3;
+pub static XRENDER_OFFSET: usize = 20;
+/// Most frames the renderer draws per second, updates coming in faster are drawn together
+pub static OPTION_MAX_FPS: usize = 60;
+pub static UI_ANCHOR: Point = Point { x: 0, y: 0 };
+/// Width and height of the level, border included
+pub static OPTION_BOARD_WIDTH: usize = 10;
+pub static OPTION_BOARD_HEIGHT: usize = 24;
+/// Smallest level a piece fits in and can be moved around, the largest that still fits a screen
+pub static OPTION_BOARD_MIN: Point = Point { x: 6, y: 8 };
+pub static OPTION_BOARD_MAX: Point = Point { x: 40, y: 60 };
+pub static OPTION_SPRINT_LINES: usize = 40;
+pub static OPTION_ULTRA_TIME_MS: u64 = 120_000;
+pub static OPTION_REPLAY_LINGER_MS: u64 = 1500;
+pub static OPTION_HUD_REFRESH_MS: u64 = 100;
+/// Length of the READY/GO countdown before play starts or resumes, 0 to skip it
+pub static OPTION_COUNTDOWN_MS: u64 = 1500;
+/// The last part of the countdown shows GO instead of READY
+pub static OPTION_COUNTDOWN_GO_MS: u64 = 500;
+/// Delayed Auto Shift, how long left/right must be held before the piece keeps moving
+pub static OPTION_DAS_MS: u64 = 170;
+/// Auto Repeat Rate, time between moves once the DAS has passed. 0 goes straight to the wall.
+pub static OPTION_ARR_MS: u64 = 50;
+/// For terminals that don't report key releases: a held key is let go when its repeats stop for
+/// this long, and repeats can't come in sooner than OPTION_KEY_REPEAT_MIN_MS after a press
+pub static OPTION_KEY_RELEASE_MS: u64 = 100;
+pub static OPTION_KEY_REPEAT_MIN_MS: u64 = 200;
+/// Gravity is this many times faster while soft drop is held. 0 drops the piece to the floor
+/// right away, without locking it (sonic drop).
+pub static OPTION_SOFT_DROP_FACTOR: usize = 20;
+/// Show where the piece would land
+pub static OPTION_GHOST: bool = true;
+/// Number of upcoming pieces shown in the side panel
+pub static OPTION_PREVIEW: usize = 1;
+pub static OPTION_PREVIEW_MAX: usize = 4;
+pub static OPTION_HIGH_SCORE_ENTRIES: usize = 10;
+pub static OPTION_NAME_MAX_LENGTH: usize = 12;
+pub static HIGH_SCORE_FILE: &str = "highscores.tsv";
+/// Recording of the last finished game, next to the high scores
+pub static LAST_REPLAY_FILE: &str = "last.replay";
+/// A bot game that doesn't top out ends after this many pieces
+pub static OPTION_BOT_MAX_PIECES: usize = 500;
+/// Key bindings and handling options, in the user's config directory
+pub static CONFIG_FILE: &str = "tootris.conf";
 
-pub(crate) static PIECE_LINE: &str = "line";
-pub(crate) static PIECE_SQUARE: &str = "square";
-pub(crate) static PIECE_PODIUM: &str = "podium";
-pub(crate) static PIECE_L: &str = "lpiece";
-pub(crate) static PIECE_J: &str = "jpiece";
-pub(crate) static PIECE_S: &str = "spiece";
-pub(crate) static PIECE_Z: &str = "zpiece";
-pub(crate) static GAME_OVER_PIECE: &str = "gameoverpiece";
+pub static PIECE_LINE: &str = "line";
+pub static PIECE_SQUARE: &str = "square";
+pub static PIECE_PODIUM: &str = "podium";
+pub static PIECE_L: &str = "lpiece";
+pub static PIECE_J: &str = "jpiece";
+pub static PIECE_S: &str = "spiece";
+pub static PIECE_Z: &str = "zpiece";
+pub static GAME_OVER_PIECE: &str = "gameoverpiece";
 
-pub(crate) static GAME_OVER_TEXT_1: &str = "██╗---██╗-██████╗-██╗---██╗----███████╗██╗---██╗-██████╗██╗--██╗██╗";
-pub(crate) static GAME_OVER_TEXT_2: &str = "╚██╗-██╔╝██╔═══██╗██║---██║----██╔════╝██║---██║██╔════╝██║-██╔╝██║";
-pub(crate) static GAME_OVER_TEXT_3: &str = "-╚████╔╝-██║---██║██║---██║----███████╗██║---██║██║-----█████╔╝-██║";
-pub(crate) static GAME_OVER_TEXT_4: &str = "--╚██╔╝--██║---██║██║---██║----╚════██║██║---██║██║-----██╔═██╗-╚═╝";
-pub(crate) static GAME_OVER_TEXT_5: &str = "---██║---╚██████╔╝╚██████╔╝----███████║╚██████╔╝╚██████╗██║--██╗██╗";
-pub(crate) static GAME_OVER_TEXT_6: &str = "---╚═╝----╚═════╝--╚═════╝-----╚══════╝-╚═════╝--╚═════╝╚═╝--╚═╝╚═╝";
-pub(crate) static GAME_OVER_TEXT_7: &str = "-------------------------------------------------------------------";
\ No newline at end of file
+pub static GAME_OVER_TEXT_1: &str = "██╗---██╗-██████╗-██╗---██╗----███████╗██╗---██╗-██████╗██╗--██╗██╗";
+pub static GAME_OVER_TEXT_2: &str = "╚██╗-██╔╝██╔═══██╗██║---██║----██╔════╝██║---██║██╔════╝██║-██╔╝██║";
+pub static GAME_OVER_TEXT_3: &str = "-╚████╔╝-██║---██║██║---██║----███████╗██║---██║██║-----█████╔╝-██║";
+pub static GAME_OVER_TEXT_4: &str = "--╚██╔╝--██║---██║██║---██║----╚════██║██║---██║██║-----██╔═██╗-╚═╝";
+pub static GAME_OVER_TEXT_5: &str = "---██║---╚██████╔╝╚██████╔╝----███████║╚██████╔╝╚██████╗██║--██╗██╗";
+pub static GAME_OVER_TEXT_6: &str = "---╚═╝----╚═════╝--╚═════╝-----╚══════╝-╚═════╝--╚═════╝╚═╝--╚═╝╚═╝";
+pub static GAME_OVER_TEXT_7: &str = "-------------------------------------------------------------------";
\ No newline at end of file
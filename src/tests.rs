@@ -1,17 +1,48 @@
 #[cfg(test)]
 mod tests {
-    use crate::game::tootris::{Rotation, BlockColor, GameBlock, Point, GameState, PlayerMove, UIHandler,
-                               GameBroadcaster, GameUpdateReceiver, Master2UICommunique, Renderer,
-                               Master2RenderCommunique, UI2RenderCommunique,
-                               UI2MasterCommunique, Communique};
+    use crate::game::tootris::{Rotation, BlockColor, GameBlock, Point, GameState, PlayerMove, UiCommand};
+    use crate::game::protocol::{GameBroadcaster, GameUpdateReceiver, Renderer, GameEvent, UiEvent,
+                                apply_changes};
 
     use crate::game::piece_types::*;
     use crate::game::piece::Piece;
 
-    use crate::settings::{PIECE_PODIUM, OPTION_TICK_BASE_MS, PIECE_Z, PIECE_S, PIECE_LINE, PIECE_J};
+    use crate::settings::{PIECE_PODIUM, PIECE_Z, PIECE_S, PIECE_LINE, PIECE_J, PIECE_SQUARE};
 
     use crate::game::game_loop_controller::EvilGameMaster;
+    use crate::game::tootris::{GameMode, GameResult, HudInfo, Randomizer, Countdown, Handling, GameConfig};
+    use crate::game::stats::{GameStats, stack_height};
+    use crate::game::replay::Replay;
+    use crate::highscores::{HighScoreEntry, HighScoreStore, format_date};
     use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+    use crate::ui::auto_shift::{AutoShift, Shift};
+    use crate::ui::keymap::{KeyAction, KeyBinding, KeyMap};
+    use crate::ui::config::{Theme, UserConfig};
+    use crate::ui::crossterm_ui::TermUI;
+    use crate::ui::text_ui::{parse_line, TextCommand};
+    use crate::cli::{Cli, Command};
+    use crate::ui::config::DisplayConfig;
+    use crate::ui::headless::{Cell, HeadlessRenderer};
+    use crate::ui::render_loop::FramePacer;
+    use crate::ui::snapshot::{assert_snapshot, diff, SnapshotRun};
+    use crossterm::style::Color;
+    use crate::game::bot;
+    use crate::game::bitboard::Occupancy;
+    use crate::game::clock::ManualClock;
+    use std::path::PathBuf;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn sized(width: usize, height: usize) -> GameConfig {
+        GameConfig { width, height, ..GameConfig::default() }
+    }
+
+    /// Runs the frames it takes gravity to move the piece a row
+    fn step_gravity(master: &mut EvilGameMaster) {
+        for _ in 0..master.gravity_frames() {
+            master.step();
+        }
+    }
 
     #[test]
     fn test_piece() {
@@ -56,7 +87,7 @@ mod tests {
         let mut my_piece: Piece = Piece::of_type(pieces.get_piece_def(PIECE_Z),
                                                  BlockColor::Blue, my_point.clone());
 
-        let mut master = EvilGameMaster::new(22, 10, Some(my_piece),
+        let mut master = EvilGameMaster::new(&sized(10, 22), Some(my_piece),
                                              None, None, None);
         let chan_master_render = channel();
         let chan_ui_master = channel();
@@ -64,9 +95,7 @@ mod tests {
         master.give_ui_receiver(GameUpdateReceiver {
             receiver: chan_ui_master.1,
         });
-        master.give_render_slave(GameBroadcaster {
-            channel_out: chan_master_render.0,
-        });
+        master.give_render_slave(GameBroadcaster::new(chan_master_render.0));
 
         let mut mock_renderer = MockCommReceiver::new();
         mock_renderer.give_master_receiver(GameUpdateReceiver
@@ -74,7 +103,7 @@ mod tests {
 
         master.resume_game();
         while master.active_piece.as_ref().unwrap().location.y < 10 {
-            master.process_game();
+            step_gravity(&mut master);
             mock_renderer.print_any_update();
         }
     }
@@ -91,12 +120,13 @@ mod tests {
         let mut other_piece: Piece = Piece::of_type(pieces.get_piece_def(PIECE_LINE), BlockColor::Magenta, other_point.clone());
         println!("{}", &my_piece);
         println!("{}", &other_piece);
-        let mut master = EvilGameMaster::new(6, 8, Some(my_piece.clone()), None, None, None);
+        let mut master = EvilGameMaster::new(&sized(8, 6), Some(my_piece.clone()), None, None, None);
         other_piece.place_in_matrix(master.level.as_mut());
+        master.sync_occupancy();
 
         master.resume_game();
         while master.active_piece.is_some() {
-            master.process_game();
+            master.step();
             if master.active_piece.is_none() {
                 break;
             }
@@ -116,14 +146,17 @@ mod tests {
         assert_eq!(matrix[3][5], GameBlock::Filled(BlockColor::Blue));
         assert_eq!(matrix[4][5], GameBlock::Filled(BlockColor::Blue));
 
-        master.process_game();
-
+        //the next piece shows up after the entry delay
+        for _ in 0..=master.are_frames {
+            assert!(master.active_piece.is_none());
+            master.step();
+        }
         assert!(master.active_piece.is_some());
         my_piece.location.x += 1;
         master.active_piece = Some(my_piece.clone());
 
         while master.active_piece.is_some() {
-            master.process_game();
+            master.step();
             if master.active_piece.is_none() {
                 break;
             }
@@ -168,7 +201,7 @@ mod tests {
 
         let my_point = Point { x: 5, y: 8 };
         let mut my_piece: Piece = Piece::of_type(pieces.get_piece_def(PIECE_PODIUM), BlockColor::Blue, my_point.clone());
-        let mut master = EvilGameMaster::new(22, 10, Some(my_piece), None, None, None);
+        let mut master = EvilGameMaster::new(&sized(10, 22), Some(my_piece), None, None, None);
 
         let ylen = master.level.len();
         let xlen = master.level[0].len();
@@ -210,51 +243,806 @@ mod tests {
         let chan_master_render = channel();
         let chan_ui_master = channel();
 
-        let master_2_render_receiver: GameUpdateReceiver<Master2RenderCommunique> = GameUpdateReceiver {
+        let master_2_render_receiver: GameUpdateReceiver<GameEvent> = GameUpdateReceiver {
             receiver: chan_master_render.1,
         };
         bitch.give_master_receiver(master_2_render_receiver);
 
-        let master_2_render_sender: GameBroadcaster<Master2RenderCommunique> = GameBroadcaster {
-            channel_out: chan_master_render.0,
-        };
+        let master_2_render_sender: GameBroadcaster<GameEvent> =
+            GameBroadcaster::new(chan_master_render.0);
         master.give_render_slave(master_2_render_sender);
-        let ui_2_master_sender: GameBroadcaster<UI2MasterCommunique> = GameBroadcaster {
-            channel_out: chan_ui_master.0,
-        };
-        let ui_2_master_receiver: GameUpdateReceiver<UI2MasterCommunique> = GameUpdateReceiver {
+        let ui_2_master_sender: GameBroadcaster<UiEvent> = GameBroadcaster::new(chan_ui_master.0);
+        let ui_2_master_receiver: GameUpdateReceiver<UiEvent> = GameUpdateReceiver {
             receiver: chan_ui_master.1,
         };
         master.give_ui_receiver(ui_2_master_receiver);
+        let clock = ManualClock::new();
+        master.set_clock(clock.shared());
+        master.countdown_ms = 0;
         master.resume_game();
         assert_eq!(master.state, GameState::Playing);
         master.process_game();
         assert_eq!(master.state, GameState::Playing);
         while master.active_piece.as_ref().unwrap().location.y < 9 {
-            let move_command = UI2MasterCommunique {
-                comm_type: Communique::Update,
-                command: None,
-                player_move: Some(PlayerMove::StepLeft),
-            };
-            ui_2_master_sender.channel_out.send(move_command);
+            let move_command = UiEvent::Move(PlayerMove::StepLeft);
+            ui_2_master_sender.send(move_command).unwrap();
             master.process_game();
+            step_gravity(&mut master);
             bitch.print_any_update();
-            assert!(master.sw.is_running());
+            assert_eq!(master.state, GameState::Playing);
         }
         println!("Score: {}", master.score);
         assert_eq!(master.active_piece.as_ref().unwrap().location.y, 9);
         //This confirms that when a piece hits the bottom and freezes, a new one spawns somewhere higher up
         while master.active_piece.is_none() || master.active_piece.as_ref().unwrap().location.y > 8 {
-            master.process_game();
+            master.step();
             bitch.print_any_update();
             bitch.print_any_update();
-            assert!(master.sw.is_running());
+            assert_eq!(master.state, GameState::Playing);
+        }
+    }
+
+    #[test]
+    fn test_high_score_store() {
+        let path = std::env::temp_dir().join(format!("tootris_scores_{}.tsv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = HighScoreStore::new(Some(path.clone()), 3);
+        let mode = GameMode::Marathon;
+        let result = |score| GameResult {
+            mode: GameMode::Marathon,
+            score,
+            lines: score / 10,
+            level: 1,
+            duration_ms: 60000,
+            stats: GameStats::new(),
+        };
+
+        assert!(!store.qualifies(&result(0)));
+        for score in [100, 300, 200].iter() {
+            assert!(store.qualifies(&result(*score)));
+            store.insert(mode, HighScoreEntry::from_result("tab\tname", &result(*score)));
+        }
+        assert!(!store.qualifies(&result(50)));
+        assert_eq!(store.rank_of(&result(250)), Some(1));
+        assert_eq!(store.insert(mode, HighScoreEntry::from_result("bob", &result(250))), Some(1));
+        let scores: Vec<usize> = store.table(mode).iter().map(|e| e.score).collect();
+        assert_eq!(scores, vec![300, 250, 200]);
+        store.save().unwrap();
+
+        let mut loaded = HighScoreStore::new(Some(path.clone()), 3);
+        loaded.load().unwrap();
+        assert_eq!(loaded.table(mode), store.table(mode));
+        assert_eq!(loaded.table(mode)[0].name, "tabname");
+        assert!(loaded.table(GameMode::Sprint).is_empty());
+        let _ = std::fs::remove_file(&path);
+
+        //sprints only count when finished, and the faster one wins
+        let sprint = |lines, duration_ms| GameResult {
+            mode: GameMode::Sprint,
+            score: 10,
+            lines,
+            level: 1,
+            duration_ms,
+            stats: GameStats::new(),
+        };
+        assert!(!store.qualifies(&sprint(39, 1000)));
+        store.insert(GameMode::Sprint, HighScoreEntry::from_result("slow", &sprint(40, 90000)));
+        assert_eq!(store.rank_of(&sprint(40, 60000)), Some(0));
+        assert_eq!(store.rank_of(&sprint(41, 95000)), Some(1));
+
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+    }
+
+    #[test]
+    fn test_game_stats() {
+        let pieces: PieceDefinitions = PieceDefinitions::new();
+        let mut master = EvilGameMaster::new(&sized(8, 8), None, None, None, None);
+        assert_eq!(stack_height(&master.level), 0);
+        assert_eq!(Occupancy::from_level(&master.level).holes(), 0);
+
+        //a flat line with an upside down podium hanging over its right end
+        //X---PPPX
+        //X----P-X
+        //XIIII--X
+        Piece::of_type(pieces.get_piece_def(PIECE_LINE), BlockColor::Blue, Point { x: 2, y: 6 })
+            .place_in_matrix(master.level.as_mut());
+        let mut podium = Piece::of_type(pieces.get_piece_def(PIECE_PODIUM), BlockColor::Green,
+                                        Point { x: 5, y: 4 });
+        podium.rotate(&Rotation::OrientDown);
+        podium.place_in_matrix(master.level.as_mut());
+        assert_eq!(stack_height(&master.level), 3);
+        assert_eq!(Occupancy::from_level(&master.level).holes(), 4);
+
+        let mut stats = GameStats::new();
+        let holes = Occupancy::from_level(&master.level).holes();
+        stats.piece_placed(podium.kind, &master.level, holes);
+        stats.piece_placed(None, &master.level, holes);
+        stats.lines_cleared(1);
+        stats.lines_cleared(4);
+        stats.lines_cleared(0);
+        stats.input();
+        stats.input();
+        stats.input();
+        assert_eq!(stats.pieces, 2);
+        assert_eq!(stats.piece_counts, [0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(stats.clears, [1, 0, 0, 1]);
+        assert_eq!(stats.max_height, 3);
+        assert_eq!(stats.holes, vec![4, 4]);
+        assert_eq!(stats.inputs_per_piece(), 1.5);
+        assert_eq!(stats.pieces_per_second(4000), 0.5);
+
+        master.stats = stats;
+        let hud = master.hud();
+        assert_eq!(hud.pieces, 2);
+        assert_eq!(hud.inputs, 3);
+        assert_eq!(HudInfo { elapsed_ms: 30000, ..hud }.actions_per_minute(), 6.0);
+        assert_eq!(HudInfo { elapsed_ms: 0, ..hud }.pieces_per_second(), 0.0);
+    }
+
+    #[test]
+    fn test_bag_randomizer() {
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, None, None, None);
+        master.randomizer = Randomizer::Bag;
+        master.countdown_ms = 0;
+        master.resume_game();
+        let mut kinds: Vec<&str> = Vec::new();
+        for _ in 0..PLAYABLE_PIECES.len() * 3 {
+            master.active_piece = None;
+            master.step();
+            kinds.push(master.active_piece.as_ref().unwrap().kind.unwrap());
+        }
+        let mut expected = PLAYABLE_PIECES.to_vec();
+        expected.sort();
+        for bag in kinds.chunks(PLAYABLE_PIECES.len()) {
+            let mut bag = bag.to_vec();
+            bag.sort();
+            assert_eq!(bag, expected);
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut level: Vec<Vec<GameBlock>> = vec![vec![GameBlock::Empty; 6]; 8];
+        level[7] = vec![GameBlock::Indestructible; 6];
+        let mut replay = Replay::new(6, 8);
+        assert!(replay.is_empty());
+        replay.record(0, &level, &[]);
+        replay.record(100, &level, &[]);
+        assert_eq!(replay.frames.len(), 1);
+        level[1][2] = GameBlock::Filled(BlockColor::Cyan);
+        replay.record(250, &level, &[]);
+        assert_eq!(replay.frames.len(), 2);
+        assert_eq!(replay.duration_ms(), 250);
+
+        let played = replay.level_at(1);
+        assert_eq!(played.len(), 8);
+        assert!(matches!(played[1][2], GameBlock::Filled(BlockColor::Cyan)));
+        assert!(matches!(played[1][1], GameBlock::Empty));
+        assert!(matches!(played[7][0], GameBlock::Indestructible));
+
+        let loaded = Replay::parse(&replay.to_text()).unwrap();
+        assert_eq!(loaded, replay);
+        assert!(Replay::parse("tootris replay 1\n6 8\n0 0000\n").is_err());
+        let wide = format!("tootris replay 1\n65 8\n0 {}\n", "0".repeat(65 * 8));
+        assert!(Replay::parse(&wide).is_err());
+        assert!(Replay::parse("something else").is_err());
+    }
+
+    #[test]
+    fn test_countdown() {
+        let chan_ui_master = channel();
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, None, None, None);
+        let clock = ManualClock::new();
+        master.set_clock(clock.shared());
+        master.give_ui_receiver(GameUpdateReceiver { receiver: chan_ui_master.1 });
+        master.countdown_ms = 200;
+        master.resume_game();
+        assert_eq!(master.state, GameState::Countdown(Countdown::Ready));
+        let spawned = master.active_piece.as_ref().unwrap().location;
+
+        //rotation is taken ahead of time, moving is not
+        for player_move in [PlayerMove::StepLeft, PlayerMove::StepDown, PlayerMove::RotateForward].iter() {
+            chan_ui_master.0.send(UiEvent::Move(*player_move)).unwrap();
+            master.process_game();
+        }
+        assert_eq!(master.active_piece.as_ref().unwrap().location, spawned);
+        assert_eq!(master.stats.inputs, 1);
+
+        assert_eq!(master.next_frame_in(), Some(Duration::from_millis(17)));
+        clock.advance(199);
+        master.process_game();
+        assert_eq!(master.state, GameState::Countdown(Countdown::Go));
+        assert_eq!(master.next_frame_in(), Some(Duration::from_millis(1)));
+        clock.advance(1);
+        master.process_game();
+        assert_eq!(master.state, GameState::Playing);
+
+        //everything the ui sent is taken in the same step
+        let x = master.active_piece.as_ref().unwrap().location.x;
+        for _ in 0..3 {
+            chan_ui_master.0.send(UiEvent::Move(PlayerMove::StepLeft)).unwrap();
+        }
+        master.process_game();
+        assert_eq!(master.active_piece.as_ref().unwrap().location.x, x - 3);
+
+        //coming back from pause counts down again
+        master.pause_game();
+        assert_eq!(master.next_frame_in(), None);
+        master.resume_game();
+        assert_eq!(master.state, GameState::Countdown(Countdown::Ready));
+    }
+
+    #[test]
+    fn test_idle_clock() {
+        let chan_ui_master = channel();
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, None, None, None);
+        let clock = ManualClock::new();
+        master.set_clock(clock.shared());
+        master.give_ui_receiver(GameUpdateReceiver { receiver: chan_ui_master.1 });
+        master.countdown_ms = 0;
+        master.are_frames = 0;
+
+        //sitting in the menu doesn't count towards the first frames of the game
+        clock.advance(10000);
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::Start(GameMode::Marathon))).unwrap();
+        master.process_game();
+        assert_eq!(master.state, GameState::Playing);
+        assert_eq!(master.game_frames, 0);
+        clock.advance(17);
+        master.process_game();
+        let spawned = master.active_piece.as_ref().unwrap().location;
+        assert!(spawned.y < 3);
+
+        //nor does sitting paused, the countdown runs in full after it
+        master.countdown_ms = 1000;
+        master.pause_game();
+        clock.advance(10000);
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::Resume)).unwrap();
+        master.process_game();
+        assert_eq!(master.state, GameState::Countdown(Countdown::Ready));
+        for _ in 0..25 {
+            clock.advance(17);
+            master.process_game();
+        }
+        assert_eq!(master.state, GameState::Countdown(Countdown::Ready));
+        assert_eq!(master.active_piece.as_ref().unwrap().location, spawned);
+    }
+
+    #[test]
+    fn test_pause_during_line_clear() {
+        let pieces: PieceDefinitions = PieceDefinitions::new();
+        let square = Piece::of_type(pieces.get_piece_def(PIECE_SQUARE), BlockColor::Cyan, Point { x: 3, y: 2 });
+        let mut master = EvilGameMaster::new(&sized(8, 10), Some(square), None, None, None);
+        master.countdown_ms = 0;
+        for x in [1, 2, 5, 6] {
+            master.level[8][x] = GameBlock::Filled(BlockColor::Blue);
+        }
+        master.level[7][1] = GameBlock::Filled(BlockColor::Blue);
+        master.sync_occupancy();
+        master.resume_game();
+        master.process_move(&PlayerMove::HardDrop);
+        assert_eq!(master.state, GameState::Tootris);
+
+        //pausing clears the row at once, and only once
+        master.pause_game();
+        assert_eq!((master.state, master.lines), (GameState::Paused, 1));
+        master.resume_game();
+        for _ in 0..10 {
+            master.step();
+        }
+        assert_eq!(master.lines, 1);
+        assert_eq!(master.level[8][1], GameBlock::Filled(BlockColor::Blue));
+        assert_eq!(master.level[8][2], GameBlock::Empty);
+        assert_eq!(master.level[8][3], GameBlock::Filled(BlockColor::Cyan));
+    }
+
+    #[test]
+    fn test_input_between_pieces() {
+        let chan_ui_master = channel();
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, None, None, None);
+        let clock = ManualClock::new();
+        master.set_clock(clock.shared());
+        master.give_ui_receiver(GameUpdateReceiver { receiver: chan_ui_master.1 });
+        master.countdown_ms = 0;
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::Start(GameMode::Marathon))).unwrap();
+        master.process_game();
+        clock.advance(17);
+        master.process_game();
+        let spawned = master.active_piece.as_ref().unwrap().location;
+        chan_ui_master.0.send(UiEvent::Move(PlayerMove::HardDrop)).unwrap();
+        clock.advance(17);
+        master.process_game();
+        assert!(master.active_piece.is_none());
+
+        //a pause during the entry delay is taken right away, the move waits for the next piece
+        chan_ui_master.0.send(UiEvent::Move(PlayerMove::StepLeft)).unwrap();
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::Pause)).unwrap();
+        master.process_game();
+        assert_eq!(master.state, GameState::Paused);
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::Resume)).unwrap();
+        while master.active_piece.is_none() {
+            clock.advance(17);
+            master.process_game();
+        }
+        master.process_game();
+        assert_eq!(master.active_piece.as_ref().unwrap().location.x, spawned.x - 1);
+        //a held key goes to the wall in one go, which isn't an input of the player's
+        let inputs = master.stats.inputs;
+        chan_ui_master.0.send(UiEvent::AutoShift(PlayerMove::StepLeft, 10)).unwrap();
+        master.process_game();
+        assert!(master.active_piece.as_ref().unwrap().location.x < spawned.x - 1);
+        assert!(!master.process_move(&PlayerMove::StepLeft));
+        assert_eq!(master.stats.inputs, inputs);
+        chan_ui_master.0.send(UiEvent::Move(PlayerMove::StepRight)).unwrap();
+        chan_ui_master.0.send(UiEvent::AutoShift(PlayerMove::StepRight, 1)).unwrap();
+        master.process_game();
+        assert_eq!(master.stats.inputs, inputs + 1);
+
+        //an exit right behind moves waits for them, the text ui quits at the end of its input
+        for event in [UiEvent::Move(PlayerMove::HardDrop), UiEvent::Move(PlayerMove::StepRight),
+                      UiEvent::Command(UiCommand::Exit)] {
+            chan_ui_master.0.send(event).unwrap();
+        }
+        master.process_game();
+        assert_eq!(master.state, GameState::Playing);
+        while master.active_piece.is_none() {
+            clock.advance(17);
+            master.process_game();
+        }
+        master.process_game();
+        assert_eq!(master.state, GameState::Exit);
+        assert_eq!(master.active_piece.as_ref().unwrap().location.x, spawned.x + 1);
+    }
+
+    #[test]
+    fn test_auto_shift() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut shift = AutoShift::new(170, 50, 100, 200);
+
+        //a tap moves once and never repeats
+        assert!(shift.key_event(PlayerMove::StepLeft, start));
+        assert_eq!(shift.poll(start + ms(150)), None);
+        assert_eq!(shift.poll(start + ms(400)), None);
+        //tapping again quickly is another move, not a repeat
+        assert!(shift.key_event(PlayerMove::StepLeft, start + ms(500)));
+        assert!(shift.key_event(PlayerMove::StepLeft, start + ms(600)));
+
+        //held: the terminal repeats the key after its own delay
+        let press = start + ms(1000);
+        assert!(shift.key_event(PlayerMove::StepRight, press));
+        assert!(!shift.key_event(PlayerMove::StepRight, press + ms(250)));
+        //the first shift is overdue, the ui shouldn't sleep
+        assert!(shift.next_change().unwrap() <= press + ms(250));
+        assert_eq!(shift.poll(press + ms(250)), Some(Shift::Steps(PlayerMove::StepRight, 1)));
+        assert!(!shift.key_event(PlayerMove::StepRight, press + ms(280)));
+        assert_eq!(shift.poll(press + ms(280)), None);
+        assert!(!shift.key_event(PlayerMove::StepRight, press + ms(310)));
+        assert_eq!(shift.poll(press + ms(310)), Some(Shift::Steps(PlayerMove::StepRight, 1)));
+        //repeats stopped, the key was let go
+        assert_eq!(shift.poll(press + ms(500)), None);
+        assert_eq!(shift.poll(press + ms(520)), None);
+
+        let mut instant = AutoShift::new(170, 0, 100, 200);
+        assert!(instant.key_event(PlayerMove::StepLeft, start));
+        assert!(!instant.key_event(PlayerMove::StepLeft, start + ms(250)));
+        assert_eq!(instant.poll(start + ms(250)), Some(Shift::ToWall(PlayerMove::StepLeft)));
+        assert!(!instant.key_event(PlayerMove::StepLeft, start + ms(280)));
+        assert_eq!(instant.poll(start + ms(280)), None);
+
+        //a terminal that reports releases allows a DAS shorter than its repeat delay, and the key
+        //stays held without any repeats until it is let go
+        let mut released = AutoShift::new(50, 50, 100, 200);
+        released.releases = true;
+        assert!(released.key_event(PlayerMove::StepLeft, start));
+        assert!(released.is_held(start + ms(60)));
+        assert_eq!(released.next_change(), Some(start + ms(50)));
+        assert_eq!(released.poll(start + ms(60)), Some(Shift::Steps(PlayerMove::StepLeft, 1)));
+        assert_eq!(released.poll(start + ms(400)), Some(Shift::Steps(PlayerMove::StepLeft, 6)));
+        assert!(!released.key_event(PlayerMove::StepLeft, start + ms(420)));
+        released.key_released(PlayerMove::StepRight);
+        assert!(released.is_held(start + ms(430)));
+        released.key_released(PlayerMove::StepLeft);
+        assert!(!released.is_held(start + ms(430)));
+        assert_eq!(released.poll(start + ms(500)), None);
+        assert_eq!(released.next_change(), None);
+    }
+
+    #[test]
+    fn test_soft_drop() {
+        let chan_ui_master = channel();
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, None, None, None);
+        let clock = ManualClock::new();
+        master.set_clock(clock.shared());
+        master.give_ui_receiver(GameUpdateReceiver { receiver: chan_ui_master.1 });
+        master.countdown_ms = 0;
+        master.soft_drop_factor = 0;
+        master.resume_game();
+        master.step();
+        let spawned = master.active_piece.as_ref().unwrap().location;
+
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::SoftDrop(true))).unwrap();
+        master.process_game();
+        master.step();
+        //sonic drop puts the piece on the floor but leaves it movable during the lock delay
+        let dropped = master.active_piece.as_ref().unwrap().location;
+        assert!(dropped.y > spawned.y + 15);
+        assert!(master.process_move(&PlayerMove::StepLeft));
+        assert_eq!(master.stats.pieces, 0);
+
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::SoftDrop(false))).unwrap();
+        master.new_game();
+        master.soft_drop_factor = 20;
+        master.resume_game();
+        //the release waits for the piece to show up
+        master.step();
+        master.process_game();
+        chan_ui_master.0.send(UiEvent::Command(UiCommand::SoftDrop(true))).unwrap();
+        let start = master.active_piece.as_ref().unwrap().location;
+        master.process_game();
+        //one row per 24 frames normally, one per frame with soft drop
+        assert_eq!(master.gravity_frames(), 24);
+        for _ in 0..5 {
+            master.step();
+        }
+        assert_eq!(master.active_piece.as_ref().unwrap().location.y, start.y + 5);
+    }
+
+    #[test]
+    fn test_keymap() {
+        let press = |code, modifiers| KeyEvent::new(code, modifiers);
+        let keys = |text: &str| text.parse::<toml::Table>().unwrap();
+        let (map, errors) = KeyMap::parse_onto(KeyMap::defaults(),
+                                               &keys("# mine\nleft = [\"h\", \"Ctrl+Left\"]\nrotate = [\n  \"x\",\n  \"Shift+z\",\n]\n"));
+        assert!(errors.is_empty());
+        assert_eq!(map.action_for(&press(KeyCode::Char('h'), KeyModifiers::NONE)), Some(KeyAction::Left));
+        assert_eq!(map.action_for(&press(KeyCode::Left, KeyModifiers::CONTROL)), Some(KeyAction::Left));
+        assert_eq!(map.action_for(&press(KeyCode::Left, KeyModifiers::NONE)), None);
+        assert_eq!(map.action_for(&press(KeyCode::Char('Z'), KeyModifiers::SHIFT)), Some(KeyAction::Rotate));
+        //what the file leaves out keeps its default
+        assert_eq!(map.action_for(&press(KeyCode::Esc, KeyModifiers::NONE)), Some(KeyAction::Quit));
+        assert_eq!(map.action_for(&press(KeyCode::Char(' '), KeyModifiers::NONE)), Some(KeyAction::HardDrop));
+        assert_eq!(KeyAction::from_name("hard_drop").unwrap().player_move(), Some(PlayerMove::HardDrop));
+        assert_eq!(KeyBinding::parse("Ctrl++").unwrap().to_string(), "Ctrl++");
+        assert_eq!(KeyBinding::parse("alt+f5").unwrap().to_string(), "Alt+F5");
+
+        //conflicts and typos throw the whole file out
+        let (map, errors) = KeyMap::parse_onto(KeyMap::defaults(), &keys("left = \"x\"\nright = [\"x\"]\n"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(map, KeyMap::defaults());
+        let (map, errors) = KeyMap::parse_onto(KeyMap::defaults(),
+                                               &keys("left = \"Lfet\"\njump = \"Space\"\npause = \"Esc\"\nquit = 3\n"));
+        assert_eq!(errors.len(), 4);
+        assert_eq!(map, KeyMap::defaults());
+    }
+
+    #[test]
+    fn test_user_config() {
+        let (config, errors) = UserConfig::parse("[keys]\nleft = \"h\"\n\n[handling]\ndas = 120\narr = 0\n\
+                                                  soft_drop = 0\nghost = false\npreview = 3\n", None);
+        assert!(errors.is_empty());
+        assert_eq!(config.handling, Handling { das_ms: 120, arr_ms: 0, soft_drop_factor: 0,
+            ghost: false, preview: 3 });
+        assert_eq!(config.keymap.keys_for(KeyAction::Left), vec![KeyBinding::parse("h").unwrap()]);
+        //writing it out and reading it back gives the same thing
+        let (again, errors) = UserConfig::parse(&config.to_file(), None);
+        assert!(errors.is_empty());
+        assert_eq!(again.to_file(), config.to_file());
+        assert_eq!(again.handling, config.handling);
+
+        //a bad handling value only resets the handling
+        let (config, errors) = UserConfig::parse("[keys]\nleft = \"h\"\n[handling]\npreview = 7\n[sound]\n", None);
+        assert_eq!(errors, vec![String::from("[handling] preview must be a number from 0 to 4, not 7"),
+                                String::from("unknown section [sound]")]);
+        assert_eq!(config.handling, Handling::default());
+        assert_eq!(config.keymap.keys_for(KeyAction::Left), vec![KeyBinding::parse("h").unwrap()]);
+        //it is TOML or nothing
+        let (config, errors) = UserConfig::parse("left = h, Left\n[handling]\ndas = 50\n", None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("line 1: "), "{:?}", errors);
+        assert_eq!(config, UserConfig::defaults(None));
+        let (_, errors) = UserConfig::parse("left = \"h\"\n[handling]\nghost = \"off\"\n", None);
+        assert_eq!(errors, vec![String::from("[handling] invalid type: string \"off\", expected a boolean in `ghost`"),
+                                String::from("'left' is outside of any section")]);
+
+        let (mut config, errors) = UserConfig::parse("[game]\nwidth = 12 # wider\nmode = \"sprint\"\n\
+                                                      [display]\ntheme = 'ascii'\nui_anchor = [2, 1]\n\
+                                                      [keys]\nleft = [\"h\", \",\", \"\\u0022\"]\n", None);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.game, GameConfig { width: 12, mode: GameMode::Sprint, ..GameConfig::default() });
+        assert_eq!(config.display.theme, Theme::Ascii);
+        assert_eq!(config.display.ui_anchor, Point { x: 2, y: 1 });
+        assert_eq!(config.keymap.keys_for(KeyAction::Left).len(), 3);
+        //overrides are checked the same way, but a bad one is refused instead of reset
+        assert!(config.set("game.height=30").is_ok());
+        assert_eq!(config.game.height, 30);
+        assert!(config.set("game.width=3").is_err());
+        assert!(config.set("display.theme=neon").is_err());
+        assert!(config.set("sound.volume=3").is_err());
+        assert_eq!(config.game.width, 12);
+        let (config, errors) = UserConfig::parse("[game]\nwidth = 12\nheight = 2\n", None);
+        assert_eq!(errors, vec![String::from("[game] height must be a number from 8 to 60, not 2")]);
+        assert_eq!(config.game, GameConfig::default());
+        let (config, errors) = UserConfig::parse("[game]\ncountdown_ms = 0\nare_frames = 0\n", None);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let master = EvilGameMaster::new(&config.game, None, None, None, None);
+        assert_eq!((master.countdown_ms, master.are_frames), (0, 0));
+        assert_eq!(master.lock_delay_frames, GameConfig::default().lock_delay_frames);
+
+        //saving the menu's settings leaves the rest of the file alone, even the parts that are wrong
+        let path = std::env::temp_dir().join(format!("tootris_config_{}.toml", std::process::id()));
+        let mine = "# my settings\n[game]\nwidth = 12 # wider\nheight = 2\n\n[handling]\n# slow\ndas = 300\nwho = 1\n";
+        std::fs::write(&path, mine).unwrap();
+        let (mut config, errors) = UserConfig::load(Some(path.clone()));
+        assert_eq!(errors.len(), 2);
+        config.handling.das_ms = 100;
+        config.keymap.bind(KeyAction::Left, vec![KeyBinding::parse("h").unwrap()]);
+        config.save().unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("# my settings\n[game]\nwidth = 12 # wider\nheight = 2\n"), "{}", saved);
+        assert!(saved.contains("# slow\ndas = 100\n") && !saved.contains("who"), "{}", saved);
+        let (again, errors) = UserConfig::load(Some(path.clone()));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(again.handling.das_ms, 100);
+        assert_eq!(again.keymap.keys_for(KeyAction::Left), vec![KeyBinding::parse("h").unwrap()]);
+        //a file that isn't TOML isn't overwritten
+        std::fs::write(&path, "width = twelve\n").unwrap();
+        assert!(config.save().is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "width = twelve\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_ghost_and_preview() {
+        let (render_sender, render_receiver) = channel();
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, Some(GameBroadcaster::new(render_sender)),
+                                             None, None);
+        master.countdown_ms = 0;
+        master.preview = 3;
+        master.resume_game();
+        master.step();
+        assert_eq!(master.upcoming().len(), 3);
+
+        master.send_render_update();
+        //the first update has the whole level, the ones after only the cells that changed
+        let mut boards = 0;
+        let mut level = None;
+        for event in render_receiver.try_iter() {
+            match event {
+                GameEvent::Board(board) => {
+                    boards += 1;
+                    level = Some(board);
+                }
+                GameEvent::BoardUpdate(changes) => apply_changes(level.as_mut().unwrap(), &changes),
+                _ => {}
+            }
+        }
+        assert_eq!(boards, 1);
+        let level = level.unwrap();
+        let ghosts: Vec<usize> = level.iter().enumerate()
+            .filter(|(_, row)| row.iter().any(|block| matches!(block, GameBlock::Ghost(_))))
+            .map(|(y, _)| y)
+            .collect();
+        //the ghost sits on the floor, the last row being the floor itself
+        assert!(!ghosts.is_empty());
+        assert_eq!(*ghosts.last().unwrap(), level.len() - 2);
+        assert!(master.level.iter().all(|row| row.iter().all(|block| !matches!(block, GameBlock::Ghost(_)))));
+
+        //the hud shows the pieces coming next, but not while paused
+        let (master_sender, master_receiver) = channel();
+        let (hud_sender, hud_receiver) = channel();
+        let mut ui = TermUI::new(None, Some(GameBroadcaster::new(hud_sender)),
+                                 Some(GameUpdateReceiver { receiver: master_receiver }),
+                                 UserConfig::defaults(None), Vec::new());
+        let mut hud_for = |state: GameState| -> Vec<String> {
+            master_sender.send(GameEvent::StateChanged(state)).unwrap();
+            master_sender.send(GameEvent::Preview(master.upcoming())).unwrap();
+            ui.update_hud();
+            let hud = hud_receiver.try_iter().filter_map(|event| match event {
+                UiEvent::Hud(hud) => Some(hud),
+                _ => None,
+            }).last().unwrap();
+            hud.iter().filter_map(|block| match block {
+                GameBlock::String(text, _) => Some(text.trim_end().to_string()),
+                _ => None,
+            }).skip_while(|row| row != "Next:").skip(1).collect()
+        };
+        let playing = hud_for(GameState::Playing);
+        assert!(playing.iter().filter(|row| !row.is_empty()).count() >= 3);
+        let paused = hud_for(GameState::Paused);
+        assert_eq!(paused.len(), playing.len());
+        assert!(paused.iter().all(|row| row.is_empty()));
+    }
+
+    #[test]
+    fn test_text_commands() {
+        assert_eq!(parse_line("3l u drop").unwrap(), vec![
+            TextCommand::Move(PlayerMove::StepLeft), TextCommand::Move(PlayerMove::StepLeft),
+            TextCommand::Move(PlayerMove::StepLeft), TextCommand::Move(PlayerMove::OrientUp),
+            TextCommand::Move(PlayerMove::HardDrop)]);
+        assert_eq!(parse_line("d D frodo").unwrap(), vec![TextCommand::Move(PlayerMove::StepDown),
+            TextCommand::Move(PlayerMove::OrientDown), TextCommand::Move(PlayerMove::OrientDown)]);
+        assert_eq!(parse_line("exit").unwrap(), vec![TextCommand::Command(UiCommand::Exit)]);
+        assert!(parse_line("  # a comment").unwrap().is_empty());
+        assert!(parse_line("r jump").is_err());
+        assert!(parse_line("0r").is_err());
+        assert!(parse_line("100r").is_err());
+
+        let mut master = EvilGameMaster::new(&sized(10, 24), None, None, None, None);
+        master.countdown_ms = 0;
+        master.resume_game();
+        master.step();
+        assert!(master.process_move(&PlayerMove::HardDrop));
+        assert!(master.active_piece.is_none());
+        assert!(master.level[master.level.len() - 2].iter().any(|block| matches!(block, GameBlock::Filled(_))));
+    }
+
+    #[test]
+    fn test_cli() {
+        let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<String>>();
+        let cli = Cli::parse(&args("--seed 7 --width=12")).unwrap();
+        assert_eq!(cli.command, Command::Play { text: false });
+        assert_eq!(cli.overrides, vec!["game.seed=7", "game.width=12"]);
+        assert_eq!(Cli::parse(&args("play --text")).unwrap().command, Command::Play { text: true });
+        assert_eq!(Cli::parse(&args("replay my.replay")).unwrap().command,
+                   Command::Replay { file: Some(PathBuf::from("my.replay")) });
+        assert_eq!(Cli::parse(&args("scores --mode sprint")).unwrap().command,
+                   Command::Scores { mode: Some(GameMode::Sprint) });
+        assert_eq!(Cli::parse(&args("bot --games 3 --pieces 20")).unwrap().command,
+                   Command::Bot { games: 3, pieces: 20 });
+        assert_eq!(Cli::parse(&args("bot -h")).unwrap().command, Command::Help);
+        assert!(Cli::parse(&args("dance")).is_err());
+        assert!(Cli::parse(&args("scores --text")).is_err());
+        assert!(Cli::parse(&args("bot --games 0")).is_err());
+        assert!(Cli::parse(&args("play --seed")).is_err());
+
+        //the same seed deals the same game, so the bot plays it the same way
+        let seeded = GameConfig { seed: Some(5), tick_base_ms: 1, ..GameConfig::default() };
+        let first = bot::play(&mut EvilGameMaster::new(&seeded, None, None, None, None), 20);
+        let second = bot::play(&mut EvilGameMaster::new(&seeded, None, None, None, None), 20);
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.stats.piece_counts, second.stats.piece_counts);
+        assert_eq!(first.stats.max_height, second.stats.max_height);
+    }
+
+    #[test]
+    fn test_headless_render() {
+        let pieces: PieceDefinitions = PieceDefinitions::new();
+        let piece = Piece::of_type(pieces.get_piece_def(PIECE_SQUARE), BlockColor::Cyan, Point { x: 3, y: 2 });
+        let chan_master_render = channel();
+        let mut master = EvilGameMaster::new(&sized(8, 10), Some(piece),
+                                             Some(GameBroadcaster::new(chan_master_render.0)), None, None);
+        master.countdown_ms = 0;
+        let display = DisplayConfig { x_offset: 2, ui_anchor: Point { x: 20, y: 0 }, theme: Theme::Ascii,
+                                     max_fps: 60 };
+        let mut renderer = HeadlessRenderer::new(Some(GameUpdateReceiver { receiver: chan_master_render.1 }),
+                                                 None, Some(master.level.clone()), display, (40, 12));
+        assert_eq!(renderer.screen().lines()[9], "  ################");
+
+        master.resume_game();
+        master.step();
+        assert!(renderer.render());
+        let lines = renderer.screen().lines();
+        assert_eq!(lines[0], "  ##............##");
+        assert_eq!(lines[2], "  ##....[][]....##");
+        assert_eq!(lines[3], "  ##....[][]....##");
+        let block = renderer.screen().cell(10, 2).unwrap();
+        assert_eq!((block.ch, block.color, block.bold), ('[', Some(Color::Cyan), true));
+        assert_eq!(renderer.screen().cell(2, 0).unwrap().color, Some(Color::Grey));
+        assert_eq!(*renderer.screen().cell(30, 5).unwrap(), Cell::BLANK);
+
+        //a smaller terminal cuts the screen off, the renderer draws everything again
+        renderer.resize(10, 4);
+        assert!(renderer.render());
+        assert_eq!(renderer.screen().text(), "  ##......\n  ##......\n  ##....[]\n  ##....[]");
+
+        //updates that came in between two renders are drawn together as the last one left things
+        renderer.resize(40, 12);
+        assert!(renderer.render());
+        step_gravity(&mut master);
+        step_gravity(&mut master);
+        let written = renderer.screen().written;
+        assert!(renderer.render());
+        //only the cells the piece left and entered are drawn, a whole well would be several hundred
+        assert!(renderer.screen().written - written < 100);
+        let lines = renderer.screen().lines();
+        assert_eq!(lines[3], "  ##............##");
+        assert_eq!(lines[4], "  ##....[][]....##");
+        assert_eq!(lines[5], "  ##....[][]....##");
+    }
+
+    #[test]
+    fn test_occupancy() {
+        let mut master = EvilGameMaster::new(&sized(6, 8), None, None, None, None);
+        let mut board = master.occupancy.clone();
+        assert_eq!(board.row(0), 0b100001);
+        assert_eq!(board.row(7), 0b111111);
+        assert!(board.is_taken(&Point { x: 0, y: 3 }));
+        assert!(!board.is_taken(&Point { x: 1, y: 3 }));
+        assert!(board.is_taken(&Point { x: 6, y: 0 }));
+
+        //a full row with a block on top, the floor doesn't count as full
+        board.fill(&(1..5).map(|x| Point { x, y: 6 }).collect::<Vec<Point>>());
+        board.fill(&[Point { x: 2, y: 5 }]);
+        assert_eq!(board.full_rows(), vec![6]);
+        board.collapse(&[6]);
+        assert_eq!(board.row(0), 0b100001);
+        assert_eq!(board.row(6), 0b100101);
+        assert!(board.full_rows().is_empty());
+        board.fill(&[Point { x: 3, y: 5 }]);
+        assert_eq!(board.holes(), 1);
+
+        //the game keeps it in step with the level through locks and clears
+        let config = GameConfig { seed: Some(3), ..sized(8, 16) };
+        let (render_sender, render_receiver) = channel();
+        master = EvilGameMaster::new(&config, None, Some(GameBroadcaster::new(render_sender)), None, None);
+        let display = DisplayConfig { x_offset: 0, ui_anchor: Point { x: 20, y: 0 }, theme: Theme::Ascii,
+                                      max_fps: 60 };
+        let mut live = HeadlessRenderer::new(Some(GameUpdateReceiver { receiver: render_receiver }), None,
+                                             Some(master.level.clone()), display, (40, 16));
+        let result = bot::play(&mut master, 60);
+        assert!(result.lines > 0);
+        assert_eq!(master.occupancy, Occupancy::from_level(&master.level));
+        //and so does the renderer, from the cells the moves, locks and clears changed. Rows moving
+        //down on a clear keep their kind of block but not always their color.
+        master.send_render_update();
+        live.render();
+        let (full_sender, full_receiver) = channel();
+        master.give_render_slave(GameBroadcaster::new(full_sender));
+        master.send_render_update();
+        let full = full_receiver.try_iter().find_map(|event| match event {
+            GameEvent::Board(board) => Some(board),
+            _ => None,
+        }).unwrap();
+        let mut expected = master.level.clone();
+        if let Some(piece) = master.active_piece.as_ref() {
+            piece.place_in_matrix(&mut expected);
         }
+        for (full_row, expected_row) in full.iter().zip(expected.iter()) {
+            for (shown, block) in full_row.iter().zip(expected_row.iter()) {
+                assert!(shown.same_look(block) || matches!(shown, GameBlock::Ghost(_)),
+                        "{:?} {:?}", shown, block);
+            }
+        }
+        let fresh = HeadlessRenderer::new(None, None, Some(full), display, (40, 16));
+        let board = config.width * 2;
+        for y in 0..config.height {
+            assert_eq!(live.screen().rows()[y][..board], fresh.screen().rows()[y][..board]);
+        }
+    }
+
+    #[test]
+    fn test_frame_pacer() {
+        let mut pacer = FramePacer::new(50);
+        let start = Instant::now();
+        assert_eq!(pacer.frame_time, Duration::from_millis(20));
+        assert_eq!(pacer.wait_before_frame(start), Duration::ZERO);
+        pacer.frame_drawn(start);
+        assert_eq!(pacer.wait_before_frame(start + Duration::from_millis(5)), Duration::from_millis(15));
+        assert_eq!(pacer.wait_before_frame(start + Duration::from_millis(30)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_render_snapshots() {
+        let config = GameConfig { seed: Some(11), ..sized(8, 12) };
+        let ascii = DisplayConfig { x_offset: 0, ui_anchor: Point { x: 20, y: 0 }, theme: Theme::Ascii,
+                                    max_fps: 60 };
+        let mut run = SnapshotRun::new(&config, ascii, (24, 14));
+        run.play("\n2l\nd u\ndrop\n\n3r D\n# into the right corner\nr drop\n\n").unwrap();
+        assert_snapshot("moves_and_drops", &run.text());
+
+        let classic = DisplayConfig { theme: Theme::Classic, ..ascii };
+        let mut run = SnapshotRun::new(&config, classic, (24, 14));
+        run.play("\n2l\n").unwrap();
+        assert_snapshot("classic_theme", &run.text());
+
+        assert_eq!(diff("frame\na\nb\nc", "frame\na\nx\nc"), "  frame\n  a\n- b\n+ x\n  c\n");
+        assert!(run.play("2l jump").is_err());
     }
 
     pub struct MockCommReceiver {
-        master_to_render_receiver: Option<GameUpdateReceiver<Master2RenderCommunique>>,
-        ui_to_render_receiver: Option<GameUpdateReceiver<UI2RenderCommunique>>,
+        master_to_render_receiver: Option<GameUpdateReceiver<GameEvent>>,
     }
 
     impl MockCommReceiver {
@@ -262,9 +1050,8 @@ mod tests {
             if self.master_to_render_receiver.is_some() {
                 let result = self.master_to_render_receiver.as_mut().unwrap().receiver.try_recv();
                 if result.is_ok() {
-                    let update = result.unwrap();
-                    if update.state.is_some() {
-                        print!("[{}] ", update.state.unwrap());
+                    if let GameEvent::StateChanged(state) = result.unwrap() {
+                        print!("[{}] ", state);
                     }
                     println!();
                 }
@@ -274,7 +1061,6 @@ mod tests {
         fn new() -> Self {
             MockCommReceiver {
                 master_to_render_receiver: None,
-                ui_to_render_receiver: None,
             }
         }
     }
@@ -283,11 +1069,11 @@ mod tests {
         fn render(&mut self) -> bool {
             true
         }
-        fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<Master2RenderCommunique>) {
+        fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>) {
             self.master_to_render_receiver = Some(receiver);
         }
 
-        fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UI2RenderCommunique>) {
+        fn give_ui_receiver(&mut self, _receiver: GameUpdateReceiver<UiEvent>) {
             todo!()
         }
     }
@@ -0,0 +1,173 @@
+use std::time::{Duration, Instant};
+
+use crate::game::tootris::PlayerMove;
+
+/**
+* What holding a direction key should do right now
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Shift {
+    /// Send the move this many times
+    Steps(PlayerMove, usize),
+    /// ARR of 0, move as far as the piece goes
+    ToWall(PlayerMove),
+}
+
+struct HeldKey {
+    player_move: PlayerMove,
+    pressed: Instant,
+    last_event: Instant,
+    /// The terminal has sent repeats for the key, or reports releases, so it really is being held
+    repeating: bool,
+    /// Time the last auto shift was due, None until the DAS has passed
+    last_shift: Option<Instant>,
+}
+
+/**
+* Delayed Auto Shift and Auto Repeat Rate for the horizontal moves.
+*
+* Terminals with the keyboard enhancement protocol report key releases, then a key is held from
+* its press until its release. Elsewhere only presses come in, so a key counts as held once the
+* terminal starts repeating it, and as released once the repeats stop coming. The terminal's own
+* repeat delay is then the shortest possible DAS. Either way the repeats only tell that the key
+* is down, the moves themselves come at the configured rate.
+*/
+pub struct AutoShift {
+    pub das: Duration,
+    /// 0 moves the piece to the wall as soon as the DAS has passed
+    pub arr: Duration,
+    /// How long after the last repeat the key counts as released
+    pub release_after: Duration,
+    /// A second event sooner than this after the press is another tap, not a repeat
+    pub repeat_after: Duration,
+    /// The terminal reports key releases, the two durations above aren't needed
+    pub releases: bool,
+    held: Option<HeldKey>,
+}
+
+impl AutoShift {
+    pub fn new(das_ms: u64, arr_ms: u64, release_ms: u64, repeat_ms: u64) -> Self {
+        AutoShift {
+            das: Duration::from_millis(das_ms),
+            arr: Duration::from_millis(arr_ms),
+            release_after: Duration::from_millis(release_ms),
+            repeat_after: Duration::from_millis(repeat_ms),
+            releases: false,
+            held: None,
+        }
+    }
+
+    /// A key event for the move came in, returns true if it is a new press that should move
+    /// the piece, false if it only repeats a key that is already held
+    pub fn key_event(&mut self, player_move: PlayerMove, now: Instant) -> bool {
+        if let Some(held) = self.held.as_mut() {
+            if held.player_move == player_move && (held.repeating || self.releases
+                || now.duration_since(held.pressed) >= self.repeat_after) {
+                held.repeating = true;
+                held.last_event = now;
+                return false;
+            }
+        }
+        self.held = Some(HeldKey {
+            player_move,
+            pressed: now,
+            last_event: now,
+            repeating: self.releases,
+            last_shift: None,
+        });
+        true
+    }
+
+    /// The terminal reported the key going up
+    pub fn key_released(&mut self, player_move: PlayerMove) {
+        if self.held.as_ref().is_some_and(|held| held.player_move == player_move) {
+            self.held = None;
+        }
+    }
+
+    /// true while the key is known to be held down, until its release or until the terminal stops
+    /// repeating it
+    pub fn is_held(&mut self, now: Instant) -> bool {
+        let held = match self.held.as_ref() {
+            Some(held) => held,
+            None => return false,
+        };
+        if self.releases {
+            return true;
+        }
+        if !held.repeating {
+            if now.duration_since(held.pressed) > self.repeat_after + self.release_after {
+                self.held = None;
+            }
+            return false;
+        }
+        if now.duration_since(held.last_event) > self.release_after {
+            self.held = None;
+            return false;
+        }
+        true
+    }
+
+    /// Forgets the held key, e.g. when a menu opens on top of the game
+    pub fn release_all(&mut self) {
+        self.held = None;
+    }
+
+    /**
+    * When poll or is_held could next give another answer without a new key event, None while
+    * no key is held. Releases are noticed just after the release time has passed.
+    */
+    pub fn next_change(&self) -> Option<Instant> {
+        let held = self.held.as_ref()?;
+        let just_after = Duration::from_millis(1);
+        if !held.repeating {
+            return Some(held.pressed + self.repeat_after + self.release_after + just_after);
+        }
+        let released = (!self.releases).then_some(held.last_event + self.release_after + just_after);
+        let shift = match held.last_shift {
+            None => Some(held.pressed + self.das),
+            Some(_) if self.arr == Duration::from_millis(0) => None,
+            Some(last) => Some(last + self.arr),
+        };
+        shift.into_iter().chain(released).min()
+    }
+
+    /// Moves that are due because a key is held down
+    pub fn poll(&mut self, now: Instant) -> Option<Shift> {
+        let held = self.held.as_mut()?;
+        if self.releases {
+            //held until the release comes in
+        } else if held.repeating {
+            if now.duration_since(held.last_event) > self.release_after {
+                self.held = None;
+                return None;
+            }
+        } else if now.duration_since(held.pressed) > self.repeat_after + self.release_after {
+            //a tap, the terminal never repeated it
+            self.held = None;
+            return None;
+        } else {
+            return None;
+        }
+
+        let charged = held.pressed + self.das;
+        if now < charged {
+            return None;
+        }
+        if self.arr == Duration::from_millis(0) {
+            if held.last_shift.is_some() {
+                return None;
+            }
+            held.last_shift = Some(now);
+            return Some(Shift::ToWall(held.player_move));
+        }
+        //the first shift happens right away, even if the terminal's repeat delay made it late
+        let last = held.last_shift.unwrap_or(now - self.arr);
+        let steps = (now.duration_since(last).as_millis() / self.arr.as_millis()) as usize;
+        if steps == 0 {
+            return None;
+        }
+        held.last_shift = Some(last + self.arr * steps as u32);
+        Some(Shift::Steps(held.player_move, steps))
+    }
+}
@@ -0,0 +1,532 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::game::tootris::{BlockColor, GameConfig, GameMode, Handling, Point};
+use crate::settings::{CONFIG_FILE, OPTION_PREVIEW_MAX, OPTION_BOARD_MIN, OPTION_BOARD_MAX, XRENDER_OFFSET,
+                      UI_ANCHOR, OPTION_MAX_FPS};
+use crate::ui::keymap::{KeyAction, KeyMap};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/**
+* How blocks are drawn
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Theme {
+    Classic,
+    /// Everything in one color
+    Mono,
+    /// Plain characters, for terminals without the block glyphs
+    Ascii,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Classic, Theme::Mono, Theme::Ascii];
+
+    /// Name of the theme in the config file
+    pub fn key(&self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::Mono => "mono",
+            Theme::Ascii => "ascii",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().find(|theme| theme.key() == key).copied()
+    }
+
+    pub fn block(&self) -> &'static str {
+        match self {
+            Theme::Ascii => "[]",
+            _ => "██",
+        }
+    }
+
+    pub fn ghost(&self) -> &'static str {
+        match self {
+            Theme::Ascii => "::",
+            _ => "░░",
+        }
+    }
+
+    pub fn border(&self) -> &'static str {
+        match self {
+            Theme::Ascii => "##",
+            _ => "██",
+        }
+    }
+
+    pub fn color(&self, color: BlockColor) -> BlockColor {
+        match self {
+            Theme::Mono => BlockColor::White,
+            _ => color,
+        }
+    }
+}
+
+/**
+* Where and how the renderer draws, read from the [display] section of the config file
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DisplayConfig {
+    /// Columns between the left edge of the terminal and the level
+    pub x_offset: usize,
+    /// Top left corner of the side panel and the full screen menus
+    pub ui_anchor: Point,
+    pub theme: Theme,
+    /// Most frames drawn per second
+    pub max_fps: usize,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            x_offset: XRENDER_OFFSET,
+            ui_anchor: UI_ANCHOR,
+            theme: Theme::Classic,
+            max_fps: OPTION_MAX_FPS,
+        }
+    }
+}
+
+static SECTIONS: [&str; 4] = ["game", "display", "keys", "handling"];
+/// What the settings menu changes and saves, everything else in the file is left as it is
+static MENU_SECTIONS: [&str; 2] = ["keys", "handling"];
+
+/**
+* Everything that can be set from the config file, a TOML file in the user's config directory
+* with [game], [display], [keys] and [handling] sections
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct UserConfig {
+    pub path: Option<PathBuf>,
+    pub game: GameConfig,
+    pub display: DisplayConfig,
+    pub keymap: KeyMap,
+    pub handling: Handling,
+}
+
+impl UserConfig {
+    pub fn defaults(path: Option<PathBuf>) -> Self {
+        UserConfig {
+            path,
+            game: GameConfig::default(),
+            display: DisplayConfig::default(),
+            keymap: KeyMap::defaults(),
+            handling: Handling::default(),
+        }
+    }
+
+    /**
+    * Anything wrong in a section gives the defaults for that section, along with what was wrong.
+    * A file that isn't TOML at all gives the defaults for everything.
+    */
+    pub fn parse(contents: &str, path: Option<PathBuf>) -> (Self, Vec<String>) {
+        let mut config = Self::defaults(path);
+        let file = match toml::from_str::<toml::Table>(contents) {
+            Ok(file) => file,
+            Err(e) => return (config, vec![syntax_error(contents, &e)]),
+        };
+        let mut errors = Vec::new();
+        for (name, value) in file.iter() {
+            if SECTIONS.contains(&name.as_str()) {
+                errors.append(&mut config.parse_section(name, value));
+            } else if value.is_table() {
+                errors.push(format!("unknown section [{}]", name));
+            } else {
+                errors.push(format!("'{}' is outside of any section", name));
+            }
+        }
+        (config, errors)
+    }
+
+    /// Reads a section on top of the current values, a section with any problem is set back to
+    /// its defaults
+    fn parse_section(&mut self, section: &str, value: &toml::Value) -> Vec<String> {
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => return vec![format!("{} must be a section, [{}]", section, section)],
+        };
+        let errors = match section {
+            "game" => read_section::<GameSection>(value).map_or_else(|e| vec![e], |read| {
+                let errors = read.apply(&mut self.game);
+                if !errors.is_empty() {
+                    self.game = GameConfig::default();
+                }
+                errors
+            }),
+            "display" => read_section::<DisplaySection>(value).map_or_else(|e| vec![e], |read| {
+                let errors = read.apply(&mut self.display);
+                if !errors.is_empty() {
+                    self.display = DisplayConfig::default();
+                }
+                errors
+            }),
+            "keys" => {
+                let (keymap, errors) = KeyMap::parse_onto(self.keymap.clone(), table);
+                self.keymap = keymap;
+                errors
+            }
+            "handling" => read_section::<HandlingSection>(value).map_or_else(|e| vec![e], |read| {
+                let errors = read.apply(&mut self.handling);
+                if !errors.is_empty() {
+                    self.handling = Handling::default();
+                }
+                errors
+            }),
+            _ => return vec![format!("unknown section [{}]", section)],
+        };
+        errors.into_iter().map(|e| format!("[{}] {}", section, e)).collect()
+    }
+
+    /**
+    * Applies a command line override such as "game.width=12". The value is read as TOML, or as
+    * plain text if it isn't any, so `display.theme=ascii` needs no quotes. Unlike the file, a bad
+    * value is an error and nothing is changed.
+    */
+    pub fn set(&mut self, setting: &str) -> Result<(), String> {
+        let (name, value) = match setting.find('=') {
+            Some(i) => (setting[..i].trim(), setting[i + 1..].trim()),
+            None => return Err(format!("{}: expected section.option=value", setting)),
+        };
+        let (section, option) = match name.find('.') {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => return Err(format!("{}: expected section.option=value, e.g. game.width=12",
+                                       setting)),
+        };
+        if !SECTIONS.contains(&section) {
+            return Err(format!("{}: unknown section '{}', use one of {}", setting, section,
+                               SECTIONS.join(", ")));
+        }
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", value)).ok()
+            .and_then(|mut read| read.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        let mut entry = toml::Table::new();
+        entry.insert(option.to_string(), value);
+        let mut changed = self.clone();
+        let errors = changed.parse_section(section, &toml::Value::Table(entry));
+        if !errors.is_empty() {
+            return Err(format!("{}: {}", setting, errors.join(", ")));
+        }
+        *self = changed;
+        Ok(())
+    }
+
+    /// Loads the config file, a missing one just means the defaults
+    pub fn load(path: Option<PathBuf>) -> (Self, Vec<String>) {
+        let contents = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(contents)) => contents,
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                let error = format!("could not read {}: {}", path.as_ref().unwrap().display(), e);
+                return (Self::defaults(path), vec![error]);
+            }
+            _ => return (Self::defaults(path), Vec::new()),
+        };
+        Self::parse(&contents, path)
+    }
+
+    pub fn to_file(&self) -> String {
+        let mut contents = String::from("# tootris settings\n");
+        let game = &self.game;
+        contents.push_str("\n[game]\n");
+        contents.push_str("# size of the level, border included\n");
+        contents.push_str(&format!("width = {}\n", game.width));
+        contents.push_str(&format!("height = {}\n", game.height));
+        contents.push_str(&format!("mode = \"{}\"\n", game.mode.key()));
+        contents.push_str(&format!("tick_base_ms = {}\n", game.tick_base_ms));
+        contents.push_str(&format!("bucket_size = {}\n", game.bucket_size));
+        contents.push_str("# READY/GO before play starts or resumes, 0 to skip it\n");
+        contents.push_str(&format!("countdown_ms = {}\n", game.countdown_ms));
+        contents.push_str("# timings in frames, the game runs at 60 a second\n");
+        contents.push_str(&format!("lock_delay_frames = {}\n", game.lock_delay_frames));
+        contents.push_str(&format!("are_frames = {}\n", game.are_frames));
+        contents.push_str(&format!("line_clear_frames = {}\n", game.line_clear_frames));
+        if let Some(seed) = game.seed {
+            contents.push_str(&format!("seed = {}\n", seed));
+        }
+
+        let display = &self.display;
+        contents.push_str("\n[display]\n");
+        contents.push_str(&format!("# one of {}\n", Theme::ALL.iter()
+            .map(|theme| theme.key()).collect::<Vec<&str>>().join(", ")));
+        contents.push_str(&format!("theme = \"{}\"\n", display.theme.key()));
+        contents.push_str(&format!("x_offset = {}\n", display.x_offset));
+        contents.push_str(&format!("ui_anchor = [{}, {}]\n", display.ui_anchor.x, display.ui_anchor.y));
+        contents.push_str("# updates coming in faster than this are drawn together\n");
+        contents.push_str(&format!("max_fps = {}\n", display.max_fps));
+
+        contents.push_str("\n[keys]\n");
+        for line in self.keymap.to_lines() {
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        let handling = &self.handling;
+        contents.push_str("\n[handling]\n");
+        contents.push_str(&format!("das = {}\n", handling.das_ms));
+        contents.push_str("# 0 moves straight to the wall\n");
+        contents.push_str(&format!("arr = {}\n", handling.arr_ms));
+        contents.push_str("# gravity multiplier while soft dropping, 0 drops to the floor\n");
+        contents.push_str(&format!("soft_drop = {}\n", handling.soft_drop_factor));
+        contents.push_str(&format!("ghost = {}\n", handling.ghost));
+        contents.push_str(&format!("preview = {}\n", handling.preview));
+        contents
+    }
+
+    /**
+    * Writes the keys and handling back to the file. Only their values are changed: the other
+    * sections, the comments and the layout stay as the file has them, so command line overrides
+    * don't end up saved. A file that isn't TOML is left alone and the error says why.
+    */
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut fresh = Self::defaults(None);
+                fresh.keymap = self.keymap.clone();
+                fresh.handling = self.handling;
+                fresh.to_file()
+            }
+            Err(e) => return Err(e),
+        };
+        let mut document = contents.parse::<toml_edit::DocumentMut>().map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, syntax_error(&contents, &e))
+        })?;
+        for section in MENU_SECTIONS.iter() {
+            if !document.get(section).is_some_and(|item| item.is_table_like()) {
+                document.insert(section, toml_edit::table());
+            }
+        }
+        let handling = &self.handling;
+        let entries = [
+            ("das", toml_edit::value(handling.das_ms as i64)),
+            ("arr", toml_edit::value(handling.arr_ms as i64)),
+            ("soft_drop", toml_edit::value(handling.soft_drop_factor as i64)),
+            ("ghost", toml_edit::value(handling.ghost)),
+            ("preview", toml_edit::value(handling.preview as i64)),
+        ];
+        set_entries(&mut document["handling"], entries.to_vec());
+        let keys: Vec<(&str, toml_edit::Item)> = KeyAction::ALL.iter().map(|action| {
+            let keys: toml_edit::Array = self.keymap.keys_for(*action).iter()
+                .map(|key| key.to_string())
+                .collect();
+            (action.name(), toml_edit::value(keys))
+        }).collect();
+        set_entries(&mut document["keys"], keys);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, document.to_string())
+    }
+}
+
+/**
+* Sets the entries of a section the menu owns, keeping the comments around the ones that were
+* there. Entries it doesn't know are dropped, they would only throw the section out when loading.
+*/
+fn set_entries(section: &mut toml_edit::Item, entries: Vec<(&str, toml_edit::Item)>) {
+    let table = section.as_table_like_mut().expect("menu sections are tables");
+    let known: Vec<&str> = entries.iter().map(|(name, _)| *name).collect();
+    let unknown: Vec<String> = table.iter()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| !known.contains(&name.as_str()))
+        .collect();
+    for name in unknown {
+        table.remove(&name);
+    }
+    for (name, item) in entries {
+        match table.get_mut(name) {
+            //only the value changes, whatever decorates it stays
+            Some(existing) if existing.is_value() => {
+                let decor = existing.as_value().unwrap().decor().clone();
+                *existing = item;
+                *existing.as_value_mut().unwrap().decor_mut() = decor;
+            }
+            _ => {
+                table.insert(name, item);
+            }
+        }
+    }
+}
+
+/// Where the file stopped being TOML, as "line N: what was wrong"
+fn syntax_error(contents: &str, error: &impl TomlError) -> String {
+    let message = error.message().lines().collect::<Vec<&str>>().join(", ");
+    match error.span() {
+        Some(span) => format!("line {}: {}", contents[..span.start].lines().count().max(1), message),
+        None => message,
+    }
+}
+
+/// The parse errors of toml and toml_edit tell the same things
+trait TomlError {
+    fn message(&self) -> &str;
+    fn span(&self) -> Option<std::ops::Range<usize>>;
+}
+
+impl TomlError for toml::de::Error {
+    fn message(&self) -> &str {
+        self.message()
+    }
+
+    fn span(&self) -> Option<std::ops::Range<usize>> {
+        self.span()
+    }
+}
+
+impl TomlError for toml_edit::TomlError {
+    fn message(&self) -> &str {
+        self.message()
+    }
+
+    fn span(&self) -> Option<std::ops::Range<usize>> {
+        self.span()
+    }
+}
+
+/// A section as the file has it: values of the wrong type and unknown options are caught here,
+/// values out of range when it is applied
+fn read_section<T: DeserializeOwned>(value: &toml::Value) -> Result<T, String> {
+    value.clone().try_into::<T>().map_err(|e| e.to_string().lines().collect::<Vec<&str>>().join(" "))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GameSection {
+    width: Option<usize>,
+    height: Option<usize>,
+    mode: Option<String>,
+    tick_base_ms: Option<usize>,
+    bucket_size: Option<usize>,
+    seed: Option<u64>,
+    countdown_ms: Option<usize>,
+    lock_delay_frames: Option<usize>,
+    are_frames: Option<usize>,
+    line_clear_frames: Option<usize>,
+}
+
+impl GameSection {
+    fn apply(self, game: &mut GameConfig) -> Vec<String> {
+        let mut errors = Vec::new();
+        game.width = in_range(&mut errors, "width", self.width, OPTION_BOARD_MIN.x, OPTION_BOARD_MAX.x)
+            .unwrap_or(game.width);
+        game.height = in_range(&mut errors, "height", self.height, OPTION_BOARD_MIN.y, OPTION_BOARD_MAX.y)
+            .unwrap_or(game.height);
+        game.tick_base_ms = in_range(&mut errors, "tick_base_ms", self.tick_base_ms, 1, 100)
+            .unwrap_or(game.tick_base_ms);
+        game.bucket_size = in_range(&mut errors, "bucket_size", self.bucket_size, 7, 100)
+            .unwrap_or(game.bucket_size);
+        game.countdown_ms = in_range(&mut errors, "countdown_ms", self.countdown_ms, 0, 5000)
+            .map_or(game.countdown_ms, |ms| ms as u64);
+        game.lock_delay_frames = in_range(&mut errors, "lock_delay_frames", self.lock_delay_frames, 0, 120)
+            .map_or(game.lock_delay_frames, |frames| frames as u64);
+        game.are_frames = in_range(&mut errors, "are_frames", self.are_frames, 0, 60)
+            .map_or(game.are_frames, |frames| frames as u64);
+        game.line_clear_frames = in_range(&mut errors, "line_clear_frames", self.line_clear_frames, 0, 60)
+            .map_or(game.line_clear_frames, |frames| frames as u64);
+        if let Some(mode) = self.mode {
+            match GameMode::from_key(&mode) {
+                Some(mode) => game.mode = mode,
+                None => {
+                    let modes: Vec<&str> = GameMode::ALL.iter().map(|mode| mode.key()).collect();
+                    errors.push(format!("mode must be one of {}, not '{}'", modes.join(", "), mode));
+                }
+            }
+        }
+        if self.seed.is_some() {
+            game.seed = self.seed;
+        }
+        errors
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DisplaySection {
+    x_offset: Option<usize>,
+    ui_anchor: Option<[usize; 2]>,
+    theme: Option<String>,
+    max_fps: Option<usize>,
+}
+
+impl DisplaySection {
+    fn apply(self, display: &mut DisplayConfig) -> Vec<String> {
+        let mut errors = Vec::new();
+        display.x_offset = in_range(&mut errors, "x_offset", self.x_offset, 0, 200)
+            .unwrap_or(display.x_offset);
+        display.max_fps = in_range(&mut errors, "max_fps", self.max_fps, 1, 240).unwrap_or(display.max_fps);
+        if let Some([x, y]) = self.ui_anchor {
+            if x > 200 || y > 200 {
+                errors.push(format!("ui_anchor must be [x, y] with numbers from 0 to 200, not [{}, {}]",
+                                    x, y));
+            } else {
+                display.ui_anchor = Point { x, y };
+            }
+        }
+        if let Some(theme) = self.theme {
+            match Theme::from_key(&theme) {
+                Some(theme) => display.theme = theme,
+                None => {
+                    let themes: Vec<&str> = Theme::ALL.iter().map(|theme| theme.key()).collect();
+                    errors.push(format!("theme must be one of {}, not '{}'", themes.join(", "), theme));
+                }
+            }
+        }
+        errors
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HandlingSection {
+    das: Option<usize>,
+    arr: Option<usize>,
+    soft_drop: Option<usize>,
+    ghost: Option<bool>,
+    preview: Option<usize>,
+}
+
+impl HandlingSection {
+    fn apply(self, handling: &mut Handling) -> Vec<String> {
+        let mut errors = Vec::new();
+        handling.das_ms = in_range(&mut errors, "das", self.das, 0, 1000)
+            .map_or(handling.das_ms, |ms| ms as u64);
+        handling.arr_ms = in_range(&mut errors, "arr", self.arr, 0, 500)
+            .map_or(handling.arr_ms, |ms| ms as u64);
+        handling.soft_drop_factor = in_range(&mut errors, "soft_drop", self.soft_drop, 0, 100)
+            .unwrap_or(handling.soft_drop_factor);
+        handling.preview = in_range(&mut errors, "preview", self.preview, 0, OPTION_PREVIEW_MAX)
+            .unwrap_or(handling.preview);
+        handling.ghost = self.ghost.unwrap_or(handling.ghost);
+        errors
+    }
+}
+
+/// The value if it was given and is in range, what was expected otherwise
+fn in_range(errors: &mut Vec<String>, name: &str, value: Option<usize>, min: usize,
+            max: usize) -> Option<usize> {
+    let value = value?;
+    if value < min || value > max {
+        errors.push(format!("{} must be a number from {} to {}, not {}", name, min, max, value));
+        return None;
+    }
+    Some(value)
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("tootris"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE))
+}
@@ -1,64 +1,185 @@
-use std::borrow::{BorrowMut, Borrow};
-
 use std::io::{Stdout, Write};
 use crossterm::{
     cursor,
     QueueableCommand, style::{self}, terminal,
 };
-use crossterm::style::{Color, Styler};
+use crossterm::style::{Color, Stylize};
 
 use crossterm::terminal::ClearType;
-use crate::game::tootris::{BlockColor, GameBlock, GameMatrix, GameState, GameUpdateReceiver, Master2RenderCommunique, Point, Renderer, UI2RenderCommunique, UiCommand};
-use crate::game::tootris::ControllerCommand;
+use crate::game::tootris::{BlockColor, GameBlock, GameMatrix, GameState, Countdown, Point, UiCommand};
+use crate::game::protocol::{level_changes, CellUpdate, GameEvent, GameUpdateReceiver, Renderer, UiEvent};
 use terminal::Clear;
-use style::{SetAttribute, Attribute, Print};
+use style::{SetAttribute, SetForegroundColor, Attribute, Print};
 use cursor::MoveTo;
-use crate::settings::{XRENDER_OFFSET, UI_ANCHOR};
+use crate::ui::config::{DisplayConfig, Theme};
+
+static PAUSED_TEXT: &str = "PAUSED";
+
+/// Where the renderer gets the size of the terminal from, columns first
+pub type TerminalSize = Box<dyn Fn() -> (u16, u16) + Send>;
 
-pub struct TermRenderer {
-    pub from_master: Option<GameUpdateReceiver<Master2RenderCommunique>>,
-    pub from_ui: Option<GameUpdateReceiver<UI2RenderCommunique>>,
-    pub out: Option<Stdout>,
+/**
+* Draws the game on a terminal through crossterm. Anything that takes bytes can stand in for
+* the terminal, together with a terminal size to match.
+*/
+pub struct TermRenderer<W: Write = Stdout> {
+    pub from_master: Option<GameUpdateReceiver<GameEvent>>,
+    pub from_ui: Option<GameUpdateReceiver<UiEvent>>,
+    pub out: Option<W>,
     pub current_matrix: Option<GameMatrix>,
     pub draw_buffer: Option<GameMatrix>,
     pub term_size: Option<(u16, u16)>,
     pub state: Option<GameState>,
     pub render_offset: Option<Point>,
     pub ui_vector: Option<Vec<GameBlock>>,
+    /// A full screen from the ui, drawn instead of the level while present
+    pub screen: Option<Vec<GameBlock>>,
+    /// Lines from the ui (the pause menu) drawn inside the masked well while paused
+    pub overlay: Option<Vec<GameBlock>>,
+    pub display: DisplayConfig,
+    pub terminal_size: TerminalSize,
+    /// Something came in that needs the whole screen drawn again, done once on the next draw
+    refresh_pending: bool,
+    /// The ui lines changed and are drawn on the next draw
+    ui_pending: bool,
 }
 
-impl TermRenderer {
+impl<W: Write> TermRenderer<W> {
+    pub fn new(from_master: Option<GameUpdateReceiver<GameEvent>>,
+               from_ui: Option<GameUpdateReceiver<UiEvent>>,
+               out: Option<W>,
+               current_matrix: Option<GameMatrix>,
+               display: DisplayConfig) -> Self {
+        TermRenderer {
+            from_master,
+            from_ui,
+            out,
+            current_matrix,
+            draw_buffer: None,
+            term_size: None,
+            state: None,
+            render_offset: None,
+            ui_vector: None,
+            screen: None,
+            overlay: None,
+            display,
+            terminal_size: Box::new(|| terminal::size().unwrap_or((80, 24))),
+            refresh_pending: false,
+            ui_pending: false,
+        }
+    }
+
+    /// Asks the given function for the terminal size instead of the real terminal
+    pub fn with_terminal_size(mut self, size: impl Fn() -> (u16, u16) + Send + 'static) -> Self {
+        self.terminal_size = Box::new(size);
+        self
+    }
+
     pub fn full_refresh(&mut self) {
+        self.refresh_pending = false;
+        self.ui_pending = false;
         self.find_render_offset();
-        terminal::enable_raw_mode().expect("It's raw mode or nothing babe");
 
         self.out.as_mut().unwrap().queue(Clear(ClearType::All)).expect("whatever");
         self.out.as_mut().unwrap().queue(cursor::DisableBlinking).expect("whatever2");
         self.out.as_mut().unwrap().queue(cursor::Hide).expect("whatever3");
-        self.draw_whole_level();
-        self.render_ui();
+        if self.screen.is_some() {
+            self.draw_screen();
+        } else if self.is_paused() {
+            self.draw_pause_overlay();
+            self.render_ui();
+        } else {
+            self.draw_whole_level();
+            self.draw_countdown();
+            self.render_ui();
+        }
         self.out.as_mut().unwrap().flush().expect("The toilet is clogged.");
-        self.term_size = Some(terminal::size().unwrap());
+        self.term_size = Some((self.terminal_size)());
     }
 
     fn find_render_offset(&mut self) {
         self.render_offset = Some(Point {
-            x: XRENDER_OFFSET,
+            x: self.display.x_offset,
             y: 0,
         });
     }
 
+    fn draw_screen(&mut self) {
+        let screen = self.screen.clone().unwrap();
+        for (i, line) in screen.into_iter().enumerate() {
+            self.draw_single(line, Point { x: self.display.ui_anchor.x, y: self.display.ui_anchor.y + i }, true);
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state == Some(GameState::Paused)
+    }
+
+    /// Draws the well with its contents blanked out, so pausing can't be used to plan ahead
+    fn draw_pause_overlay(&mut self) {
+        if self.current_matrix.is_none() {
+            return;
+        }
+        let matrix = self.current_matrix.clone().unwrap();
+        for (y, row) in matrix.iter().enumerate() {
+            for (x, block) in row.iter().enumerate() {
+                let masked = match block {
+                    GameBlock::Indestructible => GameBlock::Indestructible,
+                    _ => GameBlock::String(String::from("  "), BlockColor::White),
+                };
+                self.draw_single(masked, Point { x, y }, false);
+            }
+        }
+        let top = matrix.len() / 3;
+        self.draw_in_well(GameBlock::String(String::from(PAUSED_TEXT), BlockColor::Yellow), top);
+        if self.overlay.is_some() {
+            let overlay = self.overlay.clone().unwrap();
+            for (i, line) in overlay.into_iter().enumerate() {
+                self.draw_in_well(line, top + 2 + i);
+            }
+        }
+    }
+
+    /// READY / GO banner on top of the level
+    fn draw_countdown(&mut self) {
+        if let Some(GameState::Countdown(phase)) = self.state {
+            let y = self.current_matrix.as_ref().map_or(0, |matrix| matrix.len() / 3);
+            let color = if phase == Countdown::Go { BlockColor::Green } else { BlockColor::Yellow };
+            self.draw_in_well(GameBlock::String(format!(" {} ", phase), color), y);
+        }
+    }
+
+    fn is_counting_down(&self) -> bool {
+        matches!(self.state, Some(GameState::Countdown(_)))
+    }
+
+    /// Draws a string block horizontally centered on the well
+    fn draw_in_well(&mut self, block: GameBlock, y: usize) {
+        let offset = self.render_offset.unwrap_or(Point { x: 0, y: 0 });
+        let well_width = self.current_matrix.as_ref().map_or(0, |matrix| matrix[0].len() * 2);
+        let text_width = match &block {
+            GameBlock::String(val, _) => val.chars().count(),
+            _ => 2,
+        };
+        let x = offset.x + well_width.saturating_sub(text_width) / 2;
+        self.draw_at(block, Point { x, y: y + offset.y });
+    }
+
     fn render_ui(&mut self) {
-        if self.ui_vector.is_none() {
+        if self.ui_vector.is_none() || self.screen.is_some() {
             return;
         }
         for i in 0..self.ui_vector.as_ref().unwrap().len() {
             self.draw_single(self.ui_vector.as_ref().unwrap().get(i).unwrap().clone(),
-                             Point { x: UI_ANCHOR.x, y: UI_ANCHOR.y + i }, true);
+                             Point { x: self.display.ui_anchor.x, y: self.display.ui_anchor.y + i }, true);
         }
     }
 
-    /// Returns true if thread should continue
+    /**
+    * Takes in everything sent since the last render and draws it as one frame, so updates that
+    * came in faster than the frames are drawn only as they ended up. Returns true if thread
+    * should continue.
+    */
     pub fn maybe_render(&mut self) -> bool {
         if self.out.is_none() || self.from_master.is_none() {
             return false;
@@ -77,50 +198,70 @@ impl TermRenderer {
         if !self.check_handle_ui_updates() {
             return false;
         }
-        if self.check_if_window_changed() {
+        if self.check_if_window_changed() || self.refresh_pending {
             self.full_refresh();
-        } else if should_draw {
-            self.draw_updates();
-            self.out.as_mut().expect("no stdout?")
-                .flush().expect("forgot to flush.");
+        } else {
+            let mut drawn = false;
+            if should_draw && self.screen.is_none() && !self.is_paused() {
+                self.draw_updates();
+                self.draw_countdown();
+                drawn = true;
+            }
+            if self.ui_pending {
+                self.ui_pending = false;
+                self.render_ui();
+                drawn = true;
+            }
+            if drawn {
+                self.out.as_mut().expect("no stdout?")
+                    .flush().expect("forgot to flush.");
+            }
         }
-        return true;
+        self.state != Some(GameState::Exit)
     }
 
     fn check_if_window_changed(&mut self) -> bool {
-        let size = Some(terminal::size().unwrap());
+        let size = Some((self.terminal_size)());
         if self.term_size != size {
             return true;
         }
         return false;
     }
 
-    /// returns true if there were updates
+    /// returns true if there were level updates to draw
     fn check_handle_master_updates(&mut self) -> bool {
-        let receiver = self.from_master
-            .as_mut()
-            .unwrap().receiver.borrow_mut();
-
-        let rec = receiver.try_recv();
-        if rec.is_ok() {
-            let com = rec.unwrap();
-            if com.level.is_some() {
-                self.update_matrix(com.level.unwrap());
-                return true;
-            }
-            if com.state.is_some() {
-                self.state = com.state;
-            }
-
-            if com.command.is_some() {
-                match com.command.unwrap() {
-                    ControllerCommand::FullRefresh => {
-                        self.full_refresh();
+        let events: Vec<GameEvent> = self.from_master.as_ref().unwrap().pending().collect();
+        let mut updated = false;
+        for event in events {
+            match event {
+                GameEvent::StateChanged(state) if Some(state) != self.state => {
+                    //the banners and the masked well cover the level, so it is redrawn as a whole
+                    let was_covered = self.is_paused() || self.is_counting_down();
+                    self.state = Some(state);
+                    if was_covered || self.is_paused() || self.is_counting_down() {
+                        if !self.is_paused() {
+                            self.overlay = None;
+                        }
+                        self.refresh_pending = true;
+                    } else {
+                        updated = true;
                     }
                 }
+                GameEvent::Board(level) => {
+                    self.update_matrix(level);
+                    updated = true;
+                }
+                GameEvent::BoardUpdate(changes) => {
+                    self.update_cells(changes);
+                    updated = true;
+                }
+                GameEvent::Refresh => {
+                    self.refresh_pending = true;
+                }
+                _ => {}
             }
         }
-        return false;
+        updated
     }
 
     fn draw_whole_level(&mut self) {
@@ -137,21 +278,61 @@ impl TermRenderer {
         self.draw_updates();
     }
 
+    /**
+    * Draws the cells that changed since the last draw. The cursor only moves to the start of
+    * each run of changed cells in a row and the style only changes between cells that look
+    * different, so a piece moving a row costs a few dozen bytes rather than the whole well.
+    */
     fn draw_updates(&mut self) {
-        if self.out.is_none() {
+        if self.out.is_none() || self.draw_buffer.is_none() {
             return;
         }
-        if self.draw_buffer.is_some() {
-            self.out.as_mut().unwrap().queue(SetAttribute(Attribute::Reset))
-                .expect("NOOOOOOOOOOOOOOOOOOO!");
-
-            for y in 0..self.draw_buffer.as_ref().unwrap().len() {
-                for x in 0..self.draw_buffer.as_ref().unwrap()[0].len() {
-                    self.draw_single(
-                        self.draw_buffer.as_ref().unwrap()[y][x].clone(), Point { x, y }, false);
+        let offset = self.render_offset.unwrap_or(Point { x: 0, y: 0 });
+        let theme = self.display.theme;
+        let output = self.out.as_mut().unwrap();
+        output.queue(SetAttribute(Attribute::Reset)).expect("NOOOOOOOOOOOOOOOOOOO!");
+        let mut style = (Color::Reset, false);
+        for (y, row) in self.draw_buffer.as_mut().unwrap().iter_mut().enumerate() {
+            //the cell the cursor is on after the last one drawn in this row
+            let mut cursor_at: Option<usize> = None;
+            for (x, block) in row.iter_mut().enumerate() {
+                let (text, color, bold) = match Self::block_look(theme, block) {
+                    Some(look) => look,
+                    None => continue,
+                };
+                if cursor_at != Some(x) {
+                    output.queue(MoveTo((x * 2 + offset.x) as u16, (y + offset.y) as u16))
+                        .expect("dosh-dosh.");
                 }
+                if bold != style.1 {
+                    output.queue(SetAttribute(if bold { Attribute::Bold } else { Attribute::NormalIntensity }))
+                        .expect("ton-ton?");
+                }
+                if color != style.0 {
+                    output.queue(SetForegroundColor(color)).expect("ton-ton?");
+                }
+                style = (color, bold);
+                output.queue(Print(text)).expect("ton-ton.");
+                //strings aren't a cell wide, the next cell needs a move
+                cursor_at = if let GameBlock::String(_, _) = block { None } else { Some(x + 1) };
+                *block = GameBlock::None;
             }
         }
+        if style != (Color::Reset, false) {
+            output.queue(SetAttribute(Attribute::Reset)).expect("NOOOOOOOOOOOOOOOOOOO!");
+        }
+    }
+
+    /// What the block is drawn as: its characters, their color and whether they are bold
+    fn block_look(theme: Theme, block: &GameBlock) -> Option<(&str, Color, bool)> {
+        match block {
+            GameBlock::Filled(color) => Some((theme.block(), Self::map_color(&theme.color(*color)), true)),
+            GameBlock::Empty => Some(("..", Color::Reset, false)),
+            GameBlock::Ghost(color) => Some((theme.ghost(), Self::map_color(&theme.color(*color)), false)),
+            GameBlock::Indestructible => Some((theme.border(), Color::Grey, true)),
+            GameBlock::String(val, color) => Some((val.as_str(), Self::map_color(&theme.color(*color)), true)),
+            _ => None,
+        }
     }
 
     fn draw_single(&mut self, block: GameBlock, p: Point, override_offset: bool) {
@@ -160,30 +341,43 @@ impl TermRenderer {
         }
         let offset: Point;
         if override_offset || self.render_offset.is_none() { offset = Point { x: 0, y: 0 }; } else { offset = self.render_offset.unwrap(); }
+        self.draw_at(block, Point { x: p.x * 2 + offset.x, y: p.y + offset.y });
+    }
 
+    /// Draws the block at a terminal position, in characters rather than level cells
+    fn draw_at(&mut self, block: GameBlock, p: Point) {
+        if self.out.is_none() {
+            return;
+        }
         let output = self.out.as_mut().unwrap();
-        output.queue(MoveTo((p.x * 2 + offset.x) as u16, (p.y + offset.y) as u16))
+        output.queue(MoveTo(p.x as u16, p.y as u16))
             .expect("dosh-dosh.");
 
+        let theme = self.display.theme;
         match block {
             GameBlock::Filled(color) => {
                 output.queue(style::PrintStyledContent(
-                    "██".bold()
-                        .with(Self::map_color(&color))))
+                    theme.block().bold()
+                        .with(Self::map_color(&theme.color(color)))))
                     .expect("ton-ton?");
             }
             GameBlock::Empty => {
                 output.queue(Print("..")).expect("ton-ton.");
             }
+            GameBlock::Ghost(color) => {
+                output.queue(style::PrintStyledContent(
+                    style::style(theme.ghost()).with(Self::map_color(&theme.color(color)))))
+                    .expect("ton-ton?");
+            }
             GameBlock::Indestructible => {
                 output.queue(style::PrintStyledContent(
-                    "██".bold()
+                    theme.border().bold()
                         .with(style::Color::Grey))).expect("ton-ka-ton");
             }
             GameBlock::String(val, color) => {
                 output.queue(style::PrintStyledContent(
                     val.bold()
-                        .with(Self::map_color(&color))))
+                        .with(Self::map_color(&theme.color(color)))))
                     .expect("ton-ton?");
             }
             _ => {}
@@ -202,58 +396,82 @@ impl TermRenderer {
     }
 
     fn update_matrix(&mut self, new_matrix: GameMatrix) {
-        if self.draw_buffer.is_none() || self.current_matrix.is_none() {
-            return;
+        let changes = self.current_matrix.as_ref().and_then(|current| level_changes(current, &new_matrix));
+        match changes {
+            Some(changes) => self.update_cells(changes),
+            None => {
+                //the level is new or was resized, the old one has to be wiped off the screen
+                self.draw_buffer = Some(vec![vec![GameBlock::None; new_matrix[0].len()]; new_matrix.len()]);
+                self.current_matrix = Some(new_matrix);
+                self.refresh_pending = true;
+            }
         }
+    }
 
-        let write = self.draw_buffer.as_mut().unwrap();
-        let current = self.current_matrix.as_mut().unwrap();
-        for y in 0..new_matrix.len() {
-            for x in 0..new_matrix[0].len() {
-                if current[y][x] == new_matrix[y][x] {
-                    write[y][x] = GameBlock::None;
-                } else {
-                    write[y][x] = new_matrix[y][x].clone();
-                    current[y][x] = new_matrix[y][x].clone();
-                }
+    /// Takes in the cells that changed, the ones that differ from the screen are drawn on the next draw
+    fn update_cells(&mut self, changes: Vec<CellUpdate>) {
+        let current = match self.current_matrix.as_mut() {
+            Some(current) => current,
+            None => return,
+        };
+        let (height, width) = (current.len(), current[0].len());
+        let write = self.draw_buffer.get_or_insert_with(|| vec![vec![GameBlock::None; width]; height]);
+        for change in changes {
+            let Point { x, y } = change.at;
+            //cells not drawn yet keep their change until the next draw
+            if y < height && x < width && !current[y][x].same_look(&change.block) {
+                write[y][x] = change.block.clone();
+                current[y][x] = change.block;
             }
         }
     }
 
     fn check_handle_ui_updates(&mut self) -> bool {
-        if self.from_ui.is_some() {
-            let rec = self.from_ui.as_mut().unwrap().receiver.try_recv();
-            if rec.is_ok() {
-                let com = rec.unwrap();
-                if com.vector.is_some() {
-                    self.ui_vector = com.vector;
+        let events: Vec<UiEvent> = match self.from_ui.as_ref() {
+            Some(from_ui) => from_ui.pending().collect(),
+            None => return true,
+        };
+        for event in events {
+            match event {
+                UiEvent::Screen(screen) => {
+                    self.screen = Some(screen);
+                    self.refresh_pending = true;
                 }
-                if com.command.is_some() {
-                    match com.command.unwrap() {
-                        UiCommand::Exit => {return false;}
-                        UiCommand::RenderOffset(_) => {}
-                        UiCommand::RefreshUi => {
-                            self.render_ui();
-                        }
-                        _ => {}
-                    }
+                UiEvent::Overlay(overlay) => {
+                    self.overlay = Some(overlay);
+                    self.refresh_pending = true;
+                }
+                UiEvent::Hud(hud) => {
+                    self.ui_vector = Some(hud);
+                    self.ui_pending = true;
+                }
+                UiEvent::CloseScreen => {
+                    self.screen = None;
+                    self.refresh_pending = true;
                 }
+                UiEvent::Redraw => {
+                    self.refresh_pending = true;
+                }
+                UiEvent::Command(UiCommand::Exit) => {
+                    return false;
+                }
+                _ => {}
             }
         }
-        return true;
+        true
     }
 }
 
-impl Renderer for TermRenderer {
+impl<W: Write> Renderer for TermRenderer<W> {
     fn render(&mut self) -> bool {
         return self.maybe_render();
     }
 
-    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<Master2RenderCommunique>) {
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>) {
         self.from_master = Some(receiver);
     }
 
-    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UI2RenderCommunique>) {
+    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>) {
         self.from_ui = Some(receiver);
     }
 }
\ No newline at end of file
@@ -1,150 +1,888 @@
-use std::borrow::BorrowMut;
-use std::time::Duration;
-use crossterm::event::{Event, KeyCode, poll, read};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, read};
 
-use crate::game::tootris::{Communique, GameBroadcaster, GameState, GameUpdateReceiver, Master2UICommunique, PlayerMove, UI2MasterCommunique, UI2RenderCommunique, UiCommand, UIHandler, GameBlock, BlockColor};
+use crate::game::tootris::{GameState, PlayerMove, UiCommand, GameBlock, BlockColor, GameResult, GameMode,
+                           HudInfo, Point, Randomizer, Handling};
+use crate::game::protocol::{wakeup_channel, GameBroadcaster, GameEvent, GameUpdateReceiver, UIHandler, UiEvent,
+                            Wakeup, WakeupReceiver};
+use crate::game::piece::Piece;
+use crate::game::piece_types::{DefinitionBlock, PLAYABLE_PIECES};
+use crate::highscores::{HighScoreEntry, HighScoreStore, format_duration, table_header};
+use crate::ui::menu::{Menu, MenuAction};
+use crate::ui::auto_shift::{AutoShift, Shift};
+use crate::ui::keymap::{KeyAction, KeyBinding};
+use crate::ui::config::UserConfig;
+use crate::ui::settings::{BOARD_SIZE_PRESETS, DAS_CHOICES, ARR_CHOICES, SOFT_DROP_CHOICES};
+use crate::settings::{OPTION_KEY_RELEASE_MS, OPTION_KEY_REPEAT_MIN_MS, OPTION_PREVIEW_MAX,
+                      OPTION_NAME_MAX_LENGTH, OPTION_SPRINT_LINES, OPTION_ULTRA_TIME_MS,
+                      GAME_OVER_TEXT_1, GAME_OVER_TEXT_2, GAME_OVER_TEXT_3, GAME_OVER_TEXT_4,
+                      GAME_OVER_TEXT_5, GAME_OVER_TEXT_6, PIECE_LINE, PIECE_SQUARE, PIECE_PODIUM, PIECE_L,
+                      PIECE_J, PIECE_S, PIECE_Z};
+
+static SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+static SPARK_WIDTH: usize = 40;
+static HUD_WIDTH: usize = 18;
+/// Height of the tallest piece, as it spawns
+static PREVIEW_ROWS: usize = 3;
+static RANDOMIZERS: [Randomizer; 2] = [Randomizer::Random, Randomizer::Bag];
 
 pub struct TermUI {
-    pub to_master: Option<GameBroadcaster<UI2MasterCommunique>>,
-    pub to_render: Option<GameBroadcaster<UI2RenderCommunique>>,
-    pub from_master: Option<GameUpdateReceiver<Master2UICommunique>>,
+    pub to_master: Option<GameBroadcaster<UiEvent>>,
+    pub to_render: Option<GameBroadcaster<UiEvent>>,
+    pub from_master: Option<GameUpdateReceiver<GameEvent>>,
     pub state: Option<GameState>,
     pub score: Option<usize>,
+    pub hud: HudInfo,
     pub block_vector: Vec<GameBlock>,
+    pub high_scores: HighScoreStore,
+    pub last_result: Option<GameResult>,
+    /// Some while the player is typing a name for the high score table
+    pub name_entry: Option<String>,
+    /// true while a full screen (high scores..) is covering the level
+    pub screen_open: bool,
+    /// The last result beat everything in the high score table
+    pub personal_best: bool,
+    /// Where the last result ended up in the high score table, once saved
+    pub saved_rank: Option<usize>,
+    /// true while the master is playing back the last game
+    pub replaying: bool,
+    /// The menu being navigated, stays around while a screen opened from it is shown
+    pub menu: Option<Menu>,
+    pub board_size: Point,
+    pub randomizer: Randomizer,
+    /// Mode of the game being played, or last played
+    pub mode: GameMode,
+    pub auto_shift: AutoShift,
+    /// Only used to tell whether down is held, the master does the actual dropping
+    pub soft_drop_key: AutoShift,
+    pub soft_drop: bool,
+    pub config: UserConfig,
+    /// Problems with the config file, shown on the start menu
+    pub config_warnings: Vec<String>,
+    /// Trouble reading or writing the high score file, shown on the start and game over menus
+    pub high_score_message: Option<String>,
+    /// The action waiting for a new key in the settings menu
+    pub capturing: Option<KeyAction>,
+    /// Feedback shown at the top of the settings menu
+    pub settings_message: Option<String>,
+    /// The menu the settings were opened from
+    pub parent_menu: Option<Menu>,
+    /// The next pieces, as sent by the master
+    pub preview: Vec<Piece>,
+    /// Rung by the key reader and by the master, handle_ui sleeps on it while there is nothing to do
+    wakeup: Wakeup,
+    woken: WakeupReceiver,
+    /// Key events from the reader thread, started on the first handle_ui. None if reading failed.
+    input: Option<Receiver<Option<Event>>>,
 }
 
 impl TermUI {
-    pub fn new(to_master: Option<GameBroadcaster<UI2MasterCommunique>>,
-               to_render: Option<GameBroadcaster<UI2RenderCommunique>>,
-               from_master: Option<GameUpdateReceiver<Master2UICommunique>>) -> Self {
-        TermUI {
+    pub fn new(to_master: Option<GameBroadcaster<UiEvent>>,
+               to_render: Option<GameBroadcaster<UiEvent>>,
+               from_master: Option<GameUpdateReceiver<GameEvent>>,
+               config: UserConfig, config_warnings: Vec<String>) -> Self {
+        let (wakeup, woken) = wakeup_channel();
+        let (high_scores, loaded) = HighScoreStore::load_default();
+        let mut ui = TermUI {
             to_master,
             to_render,
             from_master,
             state: None,
             score: Some(0),
+            hud: HudInfo::default(),
             block_vector: Vec::with_capacity(10),
+            high_scores,
+            last_result: None,
+            name_entry: None,
+            screen_open: false,
+            personal_best: false,
+            saved_rank: None,
+            replaying: false,
+            menu: None,
+            board_size: Point { x: config.game.width, y: config.game.height },
+            randomizer: Randomizer::Random,
+            mode: config.game.mode,
+            auto_shift: AutoShift::new(config.handling.das_ms, config.handling.arr_ms,
+                                       OPTION_KEY_RELEASE_MS, OPTION_KEY_REPEAT_MIN_MS),
+            soft_drop_key: AutoShift::new(0, 0, OPTION_KEY_RELEASE_MS, OPTION_KEY_REPEAT_MIN_MS),
+            soft_drop: false,
+            config,
+            config_warnings,
+            high_score_message: loaded.err().map(|e| format!("Could not read high scores: {}", e)),
+            capturing: None,
+            settings_message: None,
+            parent_menu: None,
+            preview: Vec::new(),
+            wakeup,
+            woken,
+            input: None,
+        };
+        ui.apply_handling();
+        ui
+    }
+
+    /// For the master's broadcaster to the ui, so its updates wake the ui up
+    pub fn wakeup(&self) -> Wakeup {
+        self.wakeup.clone()
+    }
+
+    /// How long until a held key needs looking at again, None while no key is held
+    fn next_key_change_in(&self) -> Option<Duration> {
+        let now = Instant::now();
+        [self.auto_shift.next_change(), self.soft_drop_key.next_change()].iter()
+            .flatten()
+            .min()
+            .map(|at| at.saturating_duration_since(now))
+    }
+
+    /// Hands the handling options to the auto shift and the master
+    fn apply_handling(&mut self) {
+        let handling = self.config.handling;
+        self.auto_shift.das = Duration::from_millis(handling.das_ms);
+        self.auto_shift.arr = Duration::from_millis(handling.arr_ms);
+        if handling.preview == 0 {
+            self.preview.clear();
         }
+        self.send_to_master(UiEvent::Command(UiCommand::Handling(handling)));
     }
+    /// Takes in everything the master sent, true if any of it came in
     fn controller_update(&mut self) -> bool {
-        if self.from_master.is_none() {
-            return false;
-        }
-        let receiver = self.from_master.as_mut().unwrap().receiver.borrow_mut();
-        let rec = receiver.try_recv();
-        if rec.is_ok() {
-            let com = rec.unwrap();
-            if com.comm_type == Communique::Update {
-                if com.state.is_some() {
-                    self.state = com.state;
-                }
-                if com.score.is_some() {
-                    self.score = com.score;
+        let events: Vec<GameEvent> = match self.from_master.as_ref() {
+            Some(from_master) => from_master.pending().collect(),
+            None => return false,
+        };
+        let updated = !events.is_empty();
+        for event in events {
+            match event {
+                GameEvent::StateChanged(state) => {
+                    let replay_done = self.state == Some(GameState::Replay) && state == GameState::End;
+                    self.state = Some(state);
+                    if replay_done && self.replaying {
+                        self.replaying = false;
+                        self.show_menu();
+                    }
                 }
-                return true;
+                GameEvent::Score(score) => self.score = Some(score),
+                GameEvent::Hud(hud) => self.hud = hud,
+                GameEvent::Preview(preview) => self.preview = preview,
+                GameEvent::GameOver(result) => self.game_finished(result),
+                _ => {}
             }
         }
-        return false;
+        updated
     }
 
-    fn send_controller_command(&mut self, typ: Communique, command: Option<UiCommand>,
-                               player_move: Option<PlayerMove>) {
+    fn send_to_master(&mut self, event: UiEvent) {
         if self.to_master.is_none() {
             return;
         }
-        if self.to_master.as_mut().unwrap().channel_out.send(UI2MasterCommunique {
-            comm_type: typ,
-            command,
-            player_move,
-        }).is_err() {
+        if self.to_master.as_mut().unwrap().send(event).is_err() {
             println!("failed to send controller command");
         }
     }
 
+    /// Takes in what the master sent and passes the hud made of it on to the renderer
+    pub fn update_hud(&mut self) {
+        if self.controller_update() {
+            self.update_vector();
+            self.send_to_render(UiEvent::Hud(self.block_vector.clone()));
+        }
+    }
+
     fn update_vector(&mut self) {
+        let hud = self.hud;
         self.block_vector.clear();
-        self.block_vector.push(GameBlock::String(format!("Score: {}", self.score.unwrap()),
-                                                 BlockColor::White));
+        self.push_hud_row(format!("Score: {}", self.score.unwrap()), BlockColor::White);
+        self.push_hud_row(format!("Time:  {}", format_duration(hud.elapsed_ms)), BlockColor::White);
+        self.push_hud_row(format!("Lines: {}", hud.lines), BlockColor::White);
+        self.push_hud_row(format!("Level: {}", hud.level), BlockColor::White);
+        self.push_hud_row(format!("PPS:   {:.2}", hud.pieces_per_second()), BlockColor::Cyan);
+        self.push_hud_row(format!("APM:   {:.1}", hud.actions_per_minute()), BlockColor::Cyan);
+        if self.is_in_state(GameState::Replay) {
+            self.push_hud_row(String::from("REPLAY (any key)"), BlockColor::Yellow);
+        } else {
+            self.push_hud_row(String::new(), BlockColor::White);
+        }
+        if self.preview.is_empty() {
+            return;
+        }
+        self.push_hud_row(String::from("Next:"), BlockColor::White);
+        //the pause hides the game, what comes next included. The rows stay to blank out the pieces.
+        let hidden = self.is_in_state(GameState::Paused);
+        for piece in self.preview.clone() {
+            //every piece takes the same number of rows so the ones below don't jump around
+            for y in 0..PREVIEW_ROWS {
+                if hidden {
+                    self.push_hud_row(String::new(), BlockColor::White);
+                    continue;
+                }
+                let row: String = piece.current_matrix.get(y).map_or(String::new(), |row| {
+                    row.iter().map(|block| match block {
+                        DefinitionBlock::Blank => "  ",
+                        _ => self.config.display.theme.block(),
+                    }).collect()
+                });
+                self.push_hud_row(format!("  {}", row), piece.color);
+            }
+            self.push_hud_row(String::new(), BlockColor::White);
+        }
     }
 
-    fn send_render_command(&mut self, typ: Communique, command: Option<UiCommand>,
-                           vector: Option<Vec<GameBlock>>) {
-        if self.to_render.is_none() {
+    /// Rows are padded so a shorter value overwrites whatever was drawn there before
+    fn push_hud_row(&mut self, text: String, color: BlockColor) {
+        self.block_vector.push(GameBlock::String(format!("{:<width$}", text, width = HUD_WIDTH),
+                                                 color));
+    }
+
+    fn game_finished(&mut self, result: GameResult) {
+        self.personal_best = self.high_scores.rank_of(&result) == Some(0);
+        self.saved_rank = None;
+        let qualifies = self.high_scores.qualifies(&result);
+        self.mode = result.mode;
+        self.last_result = Some(result);
+        if qualifies {
+            self.name_entry = Some(String::new());
+            self.show_name_entry();
+        } else {
+            self.open_game_over_menu();
+        }
+    }
+
+    fn report_lines(&self) -> Vec<GameBlock> {
+        let result = self.last_result.as_ref().unwrap();
+        let stats = &result.stats;
+        let mut lines: Vec<GameBlock> = [GAME_OVER_TEXT_1, GAME_OVER_TEXT_2, GAME_OVER_TEXT_3,
+            GAME_OVER_TEXT_4, GAME_OVER_TEXT_5, GAME_OVER_TEXT_6].iter()
+            .map(|text| GameBlock::String(text.replace('-', " "), BlockColor::Magenta))
+            .collect();
+        lines.push(GameBlock::String(String::new(), BlockColor::White));
+        lines.push(GameBlock::String(format!("Score {}   Lines {}   Level {}   Time {}",
+                                             result.score, result.lines, result.level,
+                                             format_duration(result.duration_ms)),
+                                     BlockColor::Yellow));
+        lines.push(GameBlock::String(format!("Pieces {} ({:.2}/s)   Inputs per piece {:.1}",
+                                             stats.pieces, stats.pieces_per_second(result.duration_ms),
+                                             stats.inputs_per_piece()), BlockColor::White));
+        lines.push(GameBlock::String(format!("Max height {}   Holes {} (max {})", stats.max_height,
+                                             stats.current_holes(), stats.max_holes()),
+                                     BlockColor::White));
+        lines.push(GameBlock::String(format!("Clears: single {}  double {}  triple {}  tootris {}",
+                                             stats.clears[0], stats.clears[1], stats.clears[2],
+                                             stats.clears[3]), BlockColor::White));
+        let counts: Vec<String> = PLAYABLE_PIECES.iter().zip(stats.piece_counts.iter())
+            .map(|(kind, count)| format!("{} {}", piece_letter(kind), count))
+            .collect();
+        lines.push(GameBlock::String(format!("Pieces: {}", counts.join("  ")), BlockColor::White));
+        lines.push(GameBlock::String(format!("Holes over time: {}", sparkline(&stats.holes)),
+                                     BlockColor::Cyan));
+        lines.push(GameBlock::String(String::new(), BlockColor::White));
+        if !result.completed() {
+            lines.push(GameBlock::String(format!("{} not finished", result.mode), BlockColor::White));
+        } else if self.personal_best {
+            lines.push(GameBlock::String(String::from("NEW PERSONAL BEST!"), BlockColor::Yellow));
+        } else if let Some(rank) = self.saved_rank {
+            lines.push(GameBlock::String(format!("High score #{}", rank + 1), BlockColor::Yellow));
+        } else if let Some(best) = self.high_scores.table(result.mode).first() {
+            lines.push(GameBlock::String(format!("Personal best: {} ({})", best.score,
+                                                 best.duration()), BlockColor::White));
+        }
+        lines
+    }
+
+    fn high_score_lines(&self) -> Vec<GameBlock> {
+        self.high_score_message.iter()
+            .map(|message| GameBlock::String(message.clone(), BlockColor::Yellow))
+            .collect()
+    }
+
+    fn open_game_over_menu(&mut self) {
+        let mode = self.mode;
+        let mode_choice = GameMode::ALL.iter().position(|m| *m == mode).unwrap_or(0);
+        let mut header = self.report_lines();
+        header.extend(self.high_score_lines());
+        self.menu = Some(Menu::new(&format!("GAME OVER - {}", mode))
+            .header(header)
+            .entry("Retry", MenuAction::Restart)
+            .entry("View replay", MenuAction::Replay)
+            .entry_with_options("High scores",
+                                GameMode::ALL.iter().map(|mode| mode.to_string()).collect(),
+                                mode_choice, MenuAction::HighScores)
+            .entry("Main menu", MenuAction::QuitToMenu)
+            .entry("Quit", MenuAction::Quit));
+        self.show_menu();
+    }
+
+    fn show_name_entry(&mut self) {
+        let result = self.last_result.as_ref().unwrap();
+        let rank = self.high_scores.rank_of(result).unwrap_or(0);
+        let lines = vec![
+            GameBlock::String(format!("NEW HIGH SCORE! #{} in {}", rank + 1, result.mode),
+                              BlockColor::Yellow),
+            GameBlock::String(String::new(), BlockColor::White),
+            GameBlock::String(format!("Score: {}  Lines: {}  Level: {}",
+                                      result.score, result.lines, result.level), BlockColor::White),
+            GameBlock::String(String::new(), BlockColor::White),
+            GameBlock::String(format!("Enter your name: {}_",
+                                      self.name_entry.as_ref().unwrap()), BlockColor::Cyan),
+            GameBlock::String(String::new(), BlockColor::White),
+            GameBlock::String(String::from("(Enter to save, Esc to skip)"), BlockColor::White),
+        ];
+        self.open_screen(lines);
+    }
+
+    /// Returns true if the key was consumed by the name prompt
+    fn handle_name_entry(&mut self, key: KeyCode) -> bool {
+        if self.name_entry.is_none() {
+            return false;
+        }
+        match key {
+            KeyCode::Char(c) => {
+                let name = self.name_entry.as_mut().unwrap();
+                if name.chars().count() < OPTION_NAME_MAX_LENGTH && !c.is_control() {
+                    name.push(c);
+                }
+                self.show_name_entry();
+            }
+            KeyCode::Backspace => {
+                self.name_entry.as_mut().unwrap().pop();
+                self.show_name_entry();
+            }
+            KeyCode::Enter => {
+                let name = self.name_entry.take().unwrap();
+                let result = self.last_result.as_ref().unwrap();
+                self.saved_rank = self.high_scores.insert(result.mode,
+                                                          HighScoreEntry::from_result(&name, result));
+                self.high_score_message = self.high_scores.save().err()
+                    .map(|e| format!("Could not save high scores: {}", e));
+                self.open_game_over_menu();
+            }
+            KeyCode::Esc => {
+                self.name_entry = None;
+                self.personal_best = false;
+                self.open_game_over_menu();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn show_high_scores(&mut self, mode: GameMode) {
+        let mut lines = vec![
+            GameBlock::String(format!("HIGH SCORES - {}", mode), BlockColor::Yellow),
+            GameBlock::String(String::new(), BlockColor::White),
+            GameBlock::String(table_header(), BlockColor::Cyan),
+        ];
+        let table = self.high_scores.table(mode);
+        if table.is_empty() {
+            lines.push(GameBlock::String(String::from(" no scores yet"), BlockColor::White));
+        }
+        for (i, entry) in table.iter().enumerate() {
+            lines.push(GameBlock::String(entry.table_row(i + 1), BlockColor::White));
+        }
+        lines.push(GameBlock::String(String::new(), BlockColor::White));
+        lines.push(GameBlock::String(String::from("(press any key)"), BlockColor::White));
+        self.open_screen(lines);
+    }
+
+    fn open_screen(&mut self, lines: Vec<GameBlock>) {
+        self.screen_open = true;
+        self.send_to_render(UiEvent::Screen(lines));
+    }
+
+    /// Closes an informational screen, going back to the menu it was opened from if any
+    fn close_screen(&mut self) {
+        self.screen_open = false;
+        if self.menu.is_some() && !self.menu.as_ref().unwrap().compact {
+            self.show_menu();
+            return;
+        }
+        self.send_to_render(UiEvent::CloseScreen);
+        if self.menu.is_some() {
+            self.show_menu();
+        }
+    }
+
+    fn board_sizes(&self) -> Vec<Point> {
+        let mut sizes: Vec<Point> = BOARD_SIZE_PRESETS.iter()
+            .map(|(x, y)| Point { x: *x, y: *y })
+            .collect();
+        if !sizes.contains(&self.board_size) {
+            sizes.insert(0, self.board_size);
+        }
+        sizes
+    }
+
+    pub fn open_start_menu(&mut self) {
+        let sizes = self.board_sizes();
+        let size_choice = sizes.iter().position(|size| *size == self.board_size).unwrap_or(0);
+        let randomizer_choice = RANDOMIZERS.iter().position(|r| *r == self.randomizer).unwrap_or(0);
+        let mut header = Vec::new();
+        if !self.config_warnings.is_empty() {
+            header.push(GameBlock::String(String::from("Problems in the config file, using defaults:"),
+                                          BlockColor::Yellow));
+            for warning in self.config_warnings.iter() {
+                header.push(GameBlock::String(format!("  {}", warning), BlockColor::Yellow));
+            }
+        }
+        header.extend(self.high_score_lines());
+        let menu = Menu::new("T O O T R I S")
+            .header(header)
+            .entry("Marathon", MenuAction::Play(GameMode::Marathon))
+            .entry(&format!("Sprint ({} lines)", OPTION_SPRINT_LINES),
+                   MenuAction::Play(GameMode::Sprint))
+            .entry(&format!("Ultra ({} min)", OPTION_ULTRA_TIME_MS / 60000),
+                   MenuAction::Play(GameMode::Ultra))
+            .entry_with_options("Board size",
+                                sizes.iter().map(|size| format!("{} x {}", size.x, size.y)).collect(),
+                                size_choice, MenuAction::BoardSize)
+            .entry_with_options("Randomizer",
+                                RANDOMIZERS.iter().map(|r| r.to_string()).collect(),
+                                randomizer_choice, MenuAction::Randomizer)
+            .entry_with_options("High scores",
+                                GameMode::ALL.iter().map(|mode| mode.to_string()).collect(),
+                                0, MenuAction::HighScores)
+            .entry("Settings", MenuAction::Settings)
+            .entry("Quit", MenuAction::Quit);
+        self.menu = Some(menu);
+        self.show_menu();
+    }
+
+    fn open_pause_menu(&mut self) {
+        self.auto_shift.release_all();
+        self.menu = Some(Menu::new("PAUSED")
+            .compact()
+            .entry("Resume", MenuAction::Resume)
+            .entry("Restart", MenuAction::Restart)
+            .entry("Settings", MenuAction::Settings)
+            .entry("Quit to menu", MenuAction::QuitToMenu));
+        self.show_menu();
+    }
+
+    fn show_menu(&mut self) {
+        let menu = self.menu.as_ref().unwrap();
+        let lines = menu.to_lines();
+        let event = if menu.compact { UiEvent::Overlay(lines) } else { UiEvent::Screen(lines) };
+        self.send_to_render(event);
+    }
+
+    /// Returns false if the user quit
+    fn handle_menu_key(&mut self, event: KeyEvent) -> bool {
+        let pause_key = self.config.keymap.action_for(&event) == Some(KeyAction::Pause);
+        let menu = self.menu.as_mut().unwrap();
+        if pause_key && menu.has(MenuAction::Resume) {
+            return self.pick_menu_action(MenuAction::Resume);
+        }
+        match event.code {
+            KeyCode::Esc | KeyCode::Backspace if menu.has(MenuAction::Back) => {
+                return self.pick_menu_action(MenuAction::Back);
+            }
+            KeyCode::Up => menu.up(),
+            KeyCode::Down => menu.down(),
+            KeyCode::Left => {
+                menu.left();
+            }
+            KeyCode::Right => {
+                menu.right();
+            }
+            KeyCode::Enter => return self.pick_menu_entry(),
+            KeyCode::Esc | KeyCode::Backspace if menu.has(MenuAction::Resume) => {
+                return self.pick_menu_action(MenuAction::Resume);
+            }
+            KeyCode::Esc => {
+                self.send_to_master(UiEvent::Command(UiCommand::Exit));
+                return false;
+            }
+            _ => {}
+        }
+        self.apply_menu_options();
+        self.show_menu();
+        true
+    }
+
+    fn apply_menu_options(&mut self) {
+        let menu = self.menu.as_ref().unwrap();
+        if menu.has(MenuAction::BoardSize) {
+            self.board_size = self.board_sizes()[menu.choice_of(MenuAction::BoardSize)];
+        }
+        if menu.has(MenuAction::Randomizer) {
+            self.randomizer = RANDOMIZERS[menu.choice_of(MenuAction::Randomizer)];
+        }
+        if menu.has(MenuAction::Das) {
+            let handling = self.config.handling;
+            let das = choices_with(&DAS_CHOICES, handling.das_ms);
+            let arr = choices_with(&ARR_CHOICES, handling.arr_ms);
+            let soft_drop = choices_with(&SOFT_DROP_CHOICES, handling.soft_drop_factor as u64);
+            let changed = Handling {
+                das_ms: das[menu.choice_of(MenuAction::Das)],
+                arr_ms: arr[menu.choice_of(MenuAction::Arr)],
+                soft_drop_factor: soft_drop[menu.choice_of(MenuAction::SoftDrop)] as usize,
+                ghost: menu.choice_of(MenuAction::Ghost) == 1,
+                preview: menu.choice_of(MenuAction::Preview),
+            };
+            if changed != handling {
+                self.config.handling = changed;
+                self.apply_handling();
+            }
+        }
+    }
+
+    fn pick_menu_entry(&mut self) -> bool {
+        let action = self.menu.as_ref().unwrap().selected_action();
+        self.pick_menu_action(action)
+    }
+
+    fn pick_menu_action(&mut self, action: MenuAction) -> bool {
+        match action {
+            MenuAction::Play(mode) => {
+                self.apply_menu_options();
+                self.menu = None;
+                self.mode = mode;
+                self.send_to_master(UiEvent::Command(UiCommand::BoardSize(self.board_size)));
+                self.send_to_master(UiEvent::Command(UiCommand::Randomizer(self.randomizer)));
+                self.send_to_master(UiEvent::Command(UiCommand::Start(mode)));
+                self.send_to_render(UiEvent::CloseScreen);
+            }
+            MenuAction::HighScores => {
+                let mode = GameMode::ALL[self.menu.as_ref().unwrap().choice_of(MenuAction::HighScores)];
+                self.show_high_scores(mode);
+            }
+            MenuAction::Settings => {
+                self.parent_menu = self.menu.take();
+                self.settings_message = None;
+                self.open_settings(0);
+            }
+            MenuAction::Bind(action) => {
+                self.capturing = Some(action);
+                self.refresh_settings();
+            }
+            MenuAction::Back => self.close_settings(),
+            MenuAction::Quit => {
+                self.send_to_master(UiEvent::Command(UiCommand::Exit));
+                return false;
+            }
+            MenuAction::Resume => {
+                self.menu = None;
+                self.send_to_master(UiEvent::Command(UiCommand::Resume));
+            }
+            MenuAction::Restart => {
+                self.menu = None;
+                self.send_to_master(UiEvent::Command(UiCommand::New));
+                self.send_to_render(UiEvent::CloseScreen);
+            }
+            MenuAction::QuitToMenu => {
+                self.send_to_master(UiEvent::Command(UiCommand::Menu));
+                self.open_start_menu();
+            }
+            MenuAction::Replay => {
+                self.replaying = true;
+                self.send_to_master(UiEvent::Command(UiCommand::Replay));
+                self.send_to_render(UiEvent::CloseScreen);
+            }
+            MenuAction::BoardSize | MenuAction::Randomizer | MenuAction::Das | MenuAction::Arr
+            | MenuAction::SoftDrop | MenuAction::Ghost | MenuAction::Preview => {}
+        }
+        true
+    }
+
+    fn open_settings(&mut self, selected: usize) {
+        let handling = self.config.handling;
+        let mut header = Vec::new();
+        let (hint, color) = match (self.capturing, self.settings_message.as_ref()) {
+            (Some(action), _) => (format!("Press a key for {} (Esc cancels)", action), BlockColor::Yellow),
+            (None, Some(message)) => (message.clone(), BlockColor::Yellow),
+            (None, None) => (String::from("Enter on an action to rebind it, changes are saved on the way out"),
+                             BlockColor::White),
+        };
+        header.push(GameBlock::String(hint, color));
+        let mut menu = Menu::new("SETTINGS").header(header);
+        for action in KeyAction::ALL.iter() {
+            let keys: Vec<String> = self.config.keymap.keys_for(*action).iter()
+                .map(|key| key.to_string()).collect();
+            menu = menu.entry_with_options(&action.to_string(), vec![keys.join(", ")], 0,
+                                           MenuAction::Bind(*action));
+        }
+        let das = choices_with(&DAS_CHOICES, handling.das_ms);
+        let arr = choices_with(&ARR_CHOICES, handling.arr_ms);
+        let soft_drop = choices_with(&SOFT_DROP_CHOICES, handling.soft_drop_factor as u64);
+        menu = menu
+            .entry_with_options("DAS", das.iter().map(|ms| format!("{} ms", ms)).collect(),
+                                das.iter().position(|ms| *ms == handling.das_ms).unwrap(),
+                                MenuAction::Das)
+            .entry_with_options("ARR", arr.iter().map(|ms| match ms {
+                0 => String::from("instant"),
+                ms => format!("{} ms", ms),
+            }).collect(), arr.iter().position(|ms| *ms == handling.arr_ms).unwrap(), MenuAction::Arr)
+            .entry_with_options("Soft drop", soft_drop.iter().map(|factor| match factor {
+                0 => String::from("sonic"),
+                factor => format!("{}x", factor),
+            }).collect(), soft_drop.iter().position(|f| *f == handling.soft_drop_factor as u64).unwrap(),
+                                MenuAction::SoftDrop)
+            .entry_with_options("Ghost piece", vec![String::from("off"), String::from("on")],
+                                handling.ghost as usize, MenuAction::Ghost)
+            .entry_with_options("Preview", (0..=OPTION_PREVIEW_MAX).map(|n| n.to_string()).collect(),
+                                handling.preview, MenuAction::Preview)
+            .entry("Back", MenuAction::Back);
+        menu.selected = selected;
+        self.menu = Some(menu);
+        self.show_menu();
+    }
+
+    fn refresh_settings(&mut self) {
+        let selected = self.menu.as_ref().map_or(0, |menu| menu.selected);
+        self.open_settings(selected);
+    }
+
+    /// A key was pressed while waiting for a new binding
+    fn capture_key(&mut self, event: KeyEvent) {
+        let action = self.capturing.take().unwrap();
+        let key = KeyBinding::from_event(&event);
+        let taken_by = self.config.keymap.keys.iter()
+            .find(|(bound, other)| *bound == key && *other != action)
+            .map(|(_, other)| *other);
+        self.settings_message = if event.code == KeyCode::Esc {
+            None
+        } else if let Some(other) = taken_by {
+            Some(format!("{} is already bound to {}", key, other))
+        } else {
+            self.config.keymap.bind(action, vec![key]);
+            Some(format!("{} is now {}", action, key))
+        };
+        self.refresh_settings();
+    }
+
+    /// Saves the config and goes back to the menu the settings were opened from
+    fn close_settings(&mut self) {
+        if let Err(e) = self.config.save() {
+            self.settings_message = Some(format!("Could not save settings: {}", e));
+            self.refresh_settings();
+            return;
+        }
+        self.menu = self.parent_menu.take();
+        if self.menu.is_none() {
+            self.open_start_menu();
             return;
         }
+        if self.menu.as_ref().unwrap().compact {
+            self.send_to_render(UiEvent::CloseScreen);
+        }
+        self.show_menu();
+    }
+
+    /// Keys are let go when the terminal says so, instead of when their repeats stop
+    pub fn set_key_releases(&mut self, releases: bool) {
+        self.auto_shift.releases = releases;
+        self.soft_drop_key.releases = releases;
+    }
+
+    /// Only held keys care about being let go, everything else acts on the press
+    fn key_released(&mut self, event: &KeyEvent) {
+        match self.config.keymap.action_for(event) {
+            Some(KeyAction::Left) => self.auto_shift.key_released(PlayerMove::StepLeft),
+            Some(KeyAction::Right) => self.auto_shift.key_released(PlayerMove::StepRight),
+            Some(KeyAction::SoftDrop) => self.soft_drop_key.key_released(PlayerMove::StepDown),
+            _ => {}
+        }
+    }
+
+    /// Left/right go through the auto shift so holding them moves at the configured rate
+    fn shift_key(&mut self, player_move: PlayerMove) {
+        if self.auto_shift.key_event(player_move, Instant::now()) {
+            self.send_to_master(UiEvent::Move(player_move));
+        }
+    }
+
+    /// A tap is a single step, holding the key down turns on soft drop
+    fn drop_key(&mut self) {
+        if self.soft_drop_key.key_event(PlayerMove::StepDown, Instant::now()) {
+            self.send_to_master(UiEvent::Move(PlayerMove::StepDown));
+        }
+    }
+
+    fn send_auto_shift(&mut self) {
+        if self.menu.is_some() || self.screen_open || self.name_entry.is_some() {
+            self.auto_shift.release_all();
+            self.soft_drop_key.release_all();
+            self.soft_drop = false;
+            return;
+        }
+        let soft_drop = self.soft_drop_key.is_held(Instant::now());
+        if soft_drop != self.soft_drop {
+            self.soft_drop = soft_drop;
+            self.send_to_master(UiEvent::Command(UiCommand::SoftDrop(soft_drop)));
+        }
+        let (player_move, steps) = match self.auto_shift.poll(Instant::now()) {
+            Some(Shift::Steps(player_move, steps)) => (player_move, steps),
+            //the master stops the piece at the wall, extra moves do nothing
+            Some(Shift::ToWall(player_move)) => (player_move, self.board_size.x),
+            None => return,
+        };
+        self.send_to_master(UiEvent::AutoShift(player_move, steps));
+    }
 
-        if self.to_render.as_mut().unwrap().channel_out.send(UI2RenderCommunique {
-            com_type: typ,
-            vector,
-            command,
-        }).is_err() {
+    fn is_in_state(&self, state: GameState) -> bool {
+        self.state.is_some() && self.state.unwrap() == state
+    }
+
+    fn send_to_render(&mut self, event: UiEvent) {
+        if self.to_render.is_none() {
+            return;
+        }
+        if self.to_render.as_mut().unwrap().send(event).is_err() {
             println!("failed to send render command");
         }
     }
 }
 
+/// The choices with value added in order, in case the config file holds something unusual
+fn choices_with(choices: &[u64], value: u64) -> Vec<u64> {
+    let mut choices = choices.to_vec();
+    if !choices.contains(&value) {
+        choices.push(value);
+        choices.sort_unstable();
+    }
+    choices
+}
+
+fn piece_letter(kind: &str) -> &'static str {
+    match kind {
+        k if k == PIECE_LINE => "I",
+        k if k == PIECE_SQUARE => "O",
+        k if k == PIECE_PODIUM => "T",
+        k if k == PIECE_L => "L",
+        k if k == PIECE_J => "J",
+        k if k == PIECE_S => "S",
+        k if k == PIECE_Z => "Z",
+        _ => "?",
+    }
+}
+
+/// Squeezes the samples into at most SPARK_WIDTH bars, each showing the worst value of its bucket
+fn sparkline(samples: &[usize]) -> String {
+    if samples.is_empty() {
+        return String::from("-");
+    }
+    let max = (*samples.iter().max().unwrap()).max(1);
+    let bucket = samples.len().div_ceil(SPARK_WIDTH);
+    samples.chunks(bucket)
+        .map(|chunk| {
+            let value = *chunk.iter().max().unwrap();
+            SPARK_CHARS[value * (SPARK_CHARS.len() - 1) / max]
+        })
+        .collect()
+}
+
 impl UIHandler for TermUI {
     fn handle_ui(&mut self) -> bool {
-        if self.controller_update() {
-            self.update_vector();
-            self.send_render_command(Communique::Update, Some(UiCommand::RefreshUi),
-                                     Some(self.block_vector.clone()));
-        }
+        self.update_hud();
+        self.send_auto_shift();
 
-        let result = poll(Duration::from_millis(0));
-        if result.is_err() {
-            return false;
-        }
-        if result.unwrap() {
-            let event = read();
-            if event.is_err() {
-                return false;
+        let wakeup = self.wakeup.clone();
+        let input = self.input.get_or_insert_with(|| spawn_key_reader(wakeup));
+        let event = match input.try_recv() {
+            Ok(Some(event)) => event,
+            Ok(None) | Err(TryRecvError::Disconnected) => return false,
+            Err(TryRecvError::Empty) => {
+                self.woken.wait(self.next_key_change_in());
+                return true;
             }
-            match event.unwrap() {
-                Event::Key(event) => {
-                    match event.code {
-                        KeyCode::Enter => {}
-                        KeyCode::Left => {
-                            self.send_controller_command(Communique::Update, None, Some(PlayerMove::StepLeft));
-                        }
-                        KeyCode::Right => {
-                            self.send_controller_command(Communique::Update, None, Some(PlayerMove::StepRight));
-                        }
-                        KeyCode::Up => {
-                            self.send_controller_command(Communique::Update, None, Some(PlayerMove::RotateForward));
-                        }
-                        KeyCode::Down => {
-                            self.send_controller_command(Communique::Update, None, Some(PlayerMove::StepDown));
-                        }
-                        KeyCode::Esc => {
-                            self.send_controller_command(Communique::Update, Some(UiCommand::Exit), None);
-                            return false;
-                        }
-                        KeyCode::Backspace => {
-                            if self.state.is_some() && self.state.as_ref().unwrap() == &GameState::Playing {
-                                self.send_controller_command(Communique::Update, Some(UiCommand::Pause), None);
-                            } else {
-                                self.send_controller_command(Communique::Update, Some(UiCommand::Resume), None);
-                            }
+        };
+        match event {
+            Event::Key(event) => {
+                //raw mode swallows SIGINT, so ctrl+c quits from anywhere whatever the bindings say
+                if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+                    self.send_to_master(UiEvent::Command(UiCommand::Exit));
+                    return false;
+                }
+                if event.kind == KeyEventKind::Release {
+                    self.key_released(&event);
+                    return true;
+                }
+                if self.handle_name_entry(event.code) {
+                    return true;
+                }
+                if self.screen_open {
+                    self.close_screen();
+                    return true;
+                }
+                if self.replaying {
+                    if self.is_in_state(GameState::Replay) {
+                        self.send_to_master(UiEvent::Command(UiCommand::StopReplay));
+                    } else {
+                        //nothing was recorded, the replay never started
+                        self.replaying = false;
+                        self.show_menu();
+                    }
+                    return true;
+                }
+                if self.capturing.is_some() {
+                    self.capture_key(event);
+                    return true;
+                }
+                if self.menu.is_some() {
+                    return self.handle_menu_key(event);
+                }
+                let action = match self.config.keymap.action_for(&event) {
+                    Some(action) => action,
+                    None => return true,
+                };
+                match action {
+                    KeyAction::Left | KeyAction::Right => self.shift_key(action.player_move().unwrap()),
+                    KeyAction::SoftDrop => self.drop_key(),
+                    KeyAction::Rotate => {
+                        self.send_to_master(UiEvent::Move(PlayerMove::RotateForward));
+                    }
+                    //a held key would drop the next pieces too, where the terminal tells repeats apart
+                    KeyAction::HardDrop => if event.kind != KeyEventKind::Repeat {
+                        self.send_to_master(UiEvent::Move(PlayerMove::HardDrop));
+                    }
+                    KeyAction::Pause => {
+                        if self.is_in_state(GameState::Playing) || self.is_in_state(GameState::Tootris)
+                            || matches!(self.state, Some(GameState::Countdown(_))) {
+                            self.send_to_master(UiEvent::Command(UiCommand::Pause));
+                            self.open_pause_menu();
                         }
-                        _ => {}
+                    }
+                    KeyAction::Quit => {
+                        self.send_to_master(UiEvent::Command(UiCommand::Exit));
+                        return false;
                     }
                 }
-                _ => {}
             }
+            //wakes the renderer, which notices the new size and draws everything again
+            Event::Resize(_, _) => self.send_to_render(UiEvent::Redraw),
+            _ => {}
         }
         return true;
     }
 
-    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<Master2UICommunique>) {
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>) {
         self.from_master = Some(receiver);
     }
 
-    fn give_master_broadcaster(&mut self, broadcaster: GameBroadcaster<UI2MasterCommunique>) {
+    fn give_master_broadcaster(&mut self, broadcaster: GameBroadcaster<UiEvent>) {
         self.to_master = Some(broadcaster);
     }
 
-    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<UI2RenderCommunique>) {
+    fn give_render_broadcaster(&mut self, broadcaster: GameBroadcaster<UiEvent>) {
         self.to_render = Some(broadcaster);
     }
-}
\ No newline at end of file
+}
+
+/// Reads the terminal on a thread of its own, so the ui can sleep until a key or a message comes in
+fn spawn_key_reader(wakeup: Wakeup) -> Receiver<Option<Event>> {
+    let (sender, events) = channel();
+    thread::spawn(move || loop {
+        let event = read().ok();
+        let failed = event.is_none();
+        if sender.send(event).is_err() {
+            break;
+        }
+        wakeup.ring();
+        if failed {
+            break;
+        }
+    });
+    events
+}
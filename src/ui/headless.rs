@@ -0,0 +1,292 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crossterm::style::Color;
+
+use crate::game::protocol::{GameEvent, GameUpdateReceiver, Renderer, UiEvent};
+use crate::game::tootris::GameMatrix;
+use crate::ui::config::DisplayConfig;
+use crate::ui::crossterm_render::TermRenderer;
+
+/**
+* One character on the screen, with the color and weight it was printed in
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Cell {
+    pub ch: char,
+    pub color: Option<Color>,
+    pub bold: bool,
+}
+
+impl Cell {
+    pub const BLANK: Cell = Cell { ch: ' ', color: None, bold: false };
+}
+
+/**
+* A pretend terminal in memory. Takes the bytes a TermRenderer writes, follows the escape codes
+* it uses (cursor moves, clearing the screen, colors and bold) and keeps the resulting screen as
+* a grid of cells. Anything printed outside the grid is dropped, like on a real terminal.
+*/
+pub struct ScreenGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<Vec<Cell>>,
+    cursor: (usize, usize),
+    color: Option<Color>,
+    bold: bool,
+    /// The start of an escape code or a character that was cut off at the end of a write
+    pending: Vec<u8>,
+    /// Bytes written to the screen so far
+    pub written: usize,
+}
+
+impl ScreenGrid {
+    pub fn new(width: u16, height: u16) -> Self {
+        ScreenGrid {
+            width: width as usize,
+            height: height as usize,
+            cells: vec![vec![Cell::BLANK; width as usize]; height as usize],
+            cursor: (0, 0),
+            color: None,
+            bold: false,
+            pending: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.width as u16, self.height as u16)
+    }
+
+    /// Keeps what fits of the screen, like a terminal window being dragged to a new size
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.width = width as usize;
+        self.height = height as usize;
+        self.cells.resize(self.height, vec![Cell::BLANK; self.width]);
+        for row in self.cells.iter_mut() {
+            row.resize(self.width, Cell::BLANK);
+        }
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.cells.get(y).and_then(|row| row.get(x))
+    }
+
+    pub fn rows(&self) -> &[Vec<Cell>] {
+        &self.cells
+    }
+
+    /// The characters on the screen, one string per row without the blanks at the end
+    pub fn lines(&self) -> Vec<String> {
+        self.cells.iter()
+            .map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    /// The screen as text, without the blank rows at the bottom
+    pub fn text(&self) -> String {
+        let mut lines = self.lines();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+
+    fn clear(&mut self) {
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = Cell::BLANK;
+            }
+        }
+    }
+
+    fn put(&mut self, ch: char) {
+        let (x, y) = self.cursor;
+        if x < self.width && y < self.height {
+            self.cells[y][x] = Cell { ch, color: self.color, bold: self.bold };
+        }
+        self.cursor.0 += 1;
+    }
+
+    /// Works through the pending bytes, leaving only an unfinished escape code or character
+    fn process(&mut self) {
+        let mut at = 0;
+        while at < self.pending.len() {
+            let used = if self.pending[at] == 0x1b {
+                self.escape(at)
+            } else {
+                self.character(at)
+            };
+            match used {
+                Some(used) => at += used,
+                None => break,
+            }
+        }
+        self.pending.drain(..at);
+    }
+
+    /// Handles the escape code starting at the index, None if it isn't complete yet
+    fn escape(&mut self, start: usize) -> Option<usize> {
+        let bytes = &self.pending[start..];
+        if bytes.len() < 2 {
+            return None;
+        }
+        if bytes[1] != b'[' {
+            //not something crossterm sends us, skip the escape character alone
+            return Some(1);
+        }
+        let end = bytes[2..].iter().position(|byte| (0x40..=0x7e).contains(byte))? + 2;
+        let params = String::from_utf8_lossy(&bytes[2..end]).to_string();
+        let numbers: Vec<usize> = params.split(';').filter_map(|param| param.parse().ok()).collect();
+        match bytes[end] {
+            b'H' => {
+                let row = numbers.first().copied().unwrap_or(1).max(1);
+                let column = numbers.get(1).copied().unwrap_or(1).max(1);
+                self.cursor = (column - 1, row - 1);
+            }
+            b'J' if numbers.first() == Some(&2) => self.clear(),
+            b'm' => self.style(&numbers),
+            //showing and hiding the cursor and such don't change the screen
+            _ => {}
+        }
+        Some(end + 1)
+    }
+
+    fn style(&mut self, numbers: &[usize]) {
+        if numbers.is_empty() {
+            self.color = None;
+            self.bold = false;
+        }
+        let mut i = 0;
+        while i < numbers.len() {
+            match numbers[i] {
+                0 => {
+                    self.color = None;
+                    self.bold = false;
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                39 => self.color = None,
+                38 if numbers.get(i + 1) == Some(&5) && i + 2 < numbers.len() => {
+                    self.color = Some(ansi_color(numbers[i + 2]));
+                    i += 2;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Prints the utf-8 character at the index, None if only part of it is there yet
+    fn character(&mut self, start: usize) -> Option<usize> {
+        let first = self.pending[start];
+        let length = match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Some(1),
+        };
+        if self.pending.len() < start + length {
+            return None;
+        }
+        let decoded = std::str::from_utf8(&self.pending[start..start + length]).ok()
+            .and_then(|text| text.chars().next());
+        match decoded {
+            Some('\r') => self.cursor.0 = 0,
+            Some('\n') => self.cursor = (0, self.cursor.1 + 1),
+            Some(ch) => self.put(ch),
+            None => {}
+        }
+        Some(length)
+    }
+}
+
+impl Write for ScreenGrid {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len();
+        self.pending.extend_from_slice(buf);
+        self.process();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The colors crossterm asks for by their 256 color number
+fn ansi_color(value: usize) -> Color {
+    match value {
+        0 => Color::Black,
+        1 => Color::DarkRed,
+        2 => Color::DarkGreen,
+        3 => Color::DarkYellow,
+        4 => Color::DarkBlue,
+        5 => Color::DarkMagenta,
+        6 => Color::DarkCyan,
+        7 => Color::Grey,
+        8 => Color::DarkGrey,
+        9 => Color::Red,
+        10 => Color::Green,
+        11 => Color::Yellow,
+        12 => Color::Blue,
+        13 => Color::Magenta,
+        14 => Color::Cyan,
+        15 => Color::White,
+        value => Color::AnsiValue(value as u8),
+    }
+}
+
+/**
+* Renders without a terminal, for tests and tools: the usual TermRenderer drawing into a
+* ScreenGrid, so the screen can be looked at cell by cell after every render.
+*/
+pub struct HeadlessRenderer {
+    pub renderer: TermRenderer<ScreenGrid>,
+    size: Arc<Mutex<(u16, u16)>>,
+}
+
+impl HeadlessRenderer {
+    pub fn new(from_master: Option<GameUpdateReceiver<GameEvent>>,
+               from_ui: Option<GameUpdateReceiver<UiEvent>>,
+               current_matrix: Option<GameMatrix>,
+               display: DisplayConfig,
+               size: (u16, u16)) -> Self {
+        let shared_size = Arc::new(Mutex::new(size));
+        let size_for_renderer = shared_size.clone();
+        let mut renderer = TermRenderer::new(from_master, from_ui,
+                                             Some(ScreenGrid::new(size.0, size.1)),
+                                             current_matrix, display)
+            .with_terminal_size(move || *size_for_renderer.lock().unwrap());
+        renderer.full_refresh();
+        HeadlessRenderer {
+            renderer,
+            size: shared_size,
+        }
+    }
+
+    pub fn screen(&self) -> &ScreenGrid {
+        self.renderer.out.as_ref().unwrap()
+    }
+
+    /// Changes the size of the pretend terminal, the renderer notices on its next render
+    pub fn resize(&mut self, width: u16, height: u16) {
+        *self.size.lock().unwrap() = (width, height);
+        self.renderer.out.as_mut().unwrap().resize(width, height);
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn render(&mut self) -> bool {
+        self.renderer.render()
+    }
+
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>) {
+        self.renderer.give_master_receiver(receiver);
+    }
+
+    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>) {
+        self.renderer.give_ui_receiver(receiver);
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::game::tootris::{PlayerMove, UiCommand};
+
+/**
+* Everything a key can be bound to while playing. Menus keep using the arrows, Enter and Esc.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum KeyAction {
+    Left,
+    Right,
+    Rotate,
+    SoftDrop,
+    HardDrop,
+    Pause,
+    Quit,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 7] = [KeyAction::Left, KeyAction::Right, KeyAction::Rotate,
+        KeyAction::SoftDrop, KeyAction::HardDrop, KeyAction::Pause, KeyAction::Quit];
+
+    /// Name of the action in the key file
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyAction::Left => "left",
+            KeyAction::Right => "right",
+            KeyAction::Rotate => "rotate",
+            KeyAction::SoftDrop => "soft_drop",
+            KeyAction::HardDrop => "hard_drop",
+            KeyAction::Pause => "pause",
+            KeyAction::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|action| action.name() == name).copied()
+    }
+
+    pub fn player_move(&self) -> Option<PlayerMove> {
+        match self {
+            KeyAction::Left => Some(PlayerMove::StepLeft),
+            KeyAction::Right => Some(PlayerMove::StepRight),
+            KeyAction::Rotate => Some(PlayerMove::RotateForward),
+            KeyAction::SoftDrop => Some(PlayerMove::StepDown),
+            KeyAction::HardDrop => Some(PlayerMove::HardDrop),
+            _ => None,
+        }
+    }
+
+    pub fn command(&self) -> Option<UiCommand> {
+        match self {
+            KeyAction::Pause => Some(UiCommand::Pause),
+            KeyAction::Quit => Some(UiCommand::Exit),
+            _ => None,
+        }
+    }
+
+    fn default_keys(&self) -> &'static str {
+        match self {
+            KeyAction::Left => "Left",
+            KeyAction::Right => "Right",
+            KeyAction::Rotate => "Up",
+            KeyAction::SoftDrop => "Down",
+            KeyAction::HardDrop => "Space",
+            KeyAction::Pause => "Backspace",
+            KeyAction::Quit => "Esc",
+        }
+    }
+}
+
+impl Display for KeyAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyAction::Left => write!(f, "Move left"),
+            KeyAction::Right => write!(f, "Move right"),
+            KeyAction::Rotate => write!(f, "Rotate"),
+            KeyAction::SoftDrop => write!(f, "Soft drop"),
+            KeyAction::HardDrop => write!(f, "Hard drop"),
+            KeyAction::Pause => write!(f, "Pause"),
+            KeyAction::Quit => write!(f, "Quit"),
+        }
+    }
+}
+
+/**
+* A key together with the modifiers that have to be held, e.g. Ctrl+Left.
+* Shift is part of the character for printable keys, so 'A' is stored without it.
+*/
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut binding = KeyBinding { code, modifiers };
+        if let KeyCode::Char(c) = code {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                binding.code = KeyCode::Char(c.to_ascii_uppercase());
+                binding.modifiers.remove(KeyModifiers::SHIFT);
+            }
+        }
+        binding
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+
+    /// Parses things like "Left", "x", "Space", "Ctrl+Alt+F5" or "Ctrl++"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (modifier_text, key) = if text == "+" {
+            ("", "+")
+        } else if let Some(modifiers) = text.strip_suffix("++") {
+            (modifiers, "+")
+        } else {
+            match text.rfind('+') {
+                Some(i) => (&text[..i], &text[i + 1..]),
+                None => ("", text),
+            }
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_text.split('+').filter(|m| !m.is_empty()) {
+            match modifier.trim().to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.insert(KeyModifiers::CONTROL),
+                "alt" => modifiers.insert(KeyModifiers::ALT),
+                "shift" => modifiers.insert(KeyModifiers::SHIFT),
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, text)),
+            }
+        }
+        let code = parse_key_code(key).ok_or_else(|| format!("unknown key '{}'", text))?;
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{}", NAMED_KEYS.iter()
+                .find(|(_, named)| *named == code)
+                .map_or("?", |(name, _)| *name)),
+        }
+    }
+}
+
+static NAMED_KEYS: [(&str, KeyCode); 15] = [
+    ("Left", KeyCode::Left), ("Right", KeyCode::Right), ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down), ("Esc", KeyCode::Esc), ("Backspace", KeyCode::Backspace),
+    ("Enter", KeyCode::Enter), ("Tab", KeyCode::Tab), ("Home", KeyCode::Home),
+    ("End", KeyCode::End), ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown),
+    ("Delete", KeyCode::Delete), ("Insert", KeyCode::Insert), ("Space", KeyCode::Char(' ')),
+];
+
+fn parse_key_code(key: &str) -> Option<KeyCode> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    if let Some((_, code)) = NAMED_KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+        return Some(*code);
+    }
+    if key.len() > 1 && (key.starts_with('F') || key.starts_with('f')) {
+        return key[1..].parse::<u8>().ok().filter(|n| (1..=12).contains(n)).map(KeyCode::F);
+    }
+    None
+}
+
+/**
+* Which keys trigger which action. Loaded from `action = ["key", "key"]` entries in the config
+* file, with anything the file leaves out taken from the defaults.
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeyMap {
+    pub keys: Vec<(KeyBinding, KeyAction)>,
+}
+
+impl KeyMap {
+    pub fn defaults() -> Self {
+        let mut map = KeyMap { keys: Vec::new() };
+        for action in KeyAction::ALL.iter() {
+            for key in action.default_keys().split(',') {
+                map.keys.push((KeyBinding::parse(key).unwrap(), *action));
+            }
+        }
+        map
+    }
+
+    pub fn action_for(&self, event: &KeyEvent) -> Option<KeyAction> {
+        let pressed = KeyBinding::from_event(event);
+        self.keys.iter().find(|(key, _)| *key == pressed).map(|(_, action)| *action)
+    }
+
+    pub fn keys_for(&self, action: KeyAction) -> Vec<KeyBinding> {
+        self.keys.iter().filter(|(_, a)| *a == action).map(|(key, _)| *key).collect()
+    }
+
+    /// Replaces the keys of the action
+    pub fn bind(&mut self, action: KeyAction, keys: Vec<KeyBinding>) {
+        self.keys.retain(|(_, a)| *a != action);
+        for key in keys {
+            self.keys.push((key, action));
+        }
+    }
+
+    /// Descriptions of every key bound to more than one action
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (i, (key, action)) in self.keys.iter().enumerate() {
+            if let Some((_, other)) = self.keys[..i].iter()
+                .find(|(other_key, other)| other_key == key && other != action) {
+                conflicts.push(format!("{} is bound to both {} and {}", key, other.name(),
+                                       action.name()));
+            }
+        }
+        conflicts
+    }
+
+    /// Reads the `action = ["key", "key"]` entries of the keys section on top of the given map,
+    /// actions it leaves out keep their keys. Any problem gives the defaults back, along with
+    /// what was wrong.
+    pub fn parse_onto(mut map: KeyMap, section: &toml::Table) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        for (name, value) in section.iter() {
+            let action = match KeyAction::from_name(name) {
+                Some(action) => action,
+                None => {
+                    errors.push(format!("unknown action '{}'", name));
+                    continue;
+                }
+            };
+            let texts = match value {
+                toml::Value::String(key) => vec![key.as_str()],
+                toml::Value::Array(keys) if keys.iter().all(|key| key.is_str()) => {
+                    keys.iter().filter_map(|key| key.as_str()).collect()
+                }
+                _ => {
+                    errors.push(format!("{} must be a key or a list of keys, not {}", name, value));
+                    continue;
+                }
+            };
+            let mut keys = Vec::new();
+            let known_errors = errors.len();
+            for text in texts {
+                match KeyBinding::parse(text) {
+                    Ok(key) if !keys.contains(&key) => keys.push(key),
+                    Ok(_) => {}
+                    Err(e) => errors.push(e),
+                }
+            }
+            if keys.is_empty() {
+                if errors.len() == known_errors {
+                    errors.push(format!("no keys for {}", name));
+                }
+                continue;
+            }
+            map.bind(action, keys);
+        }
+        errors.append(&mut map.conflicts());
+        if !errors.is_empty() {
+            return (Self::defaults(), errors);
+        }
+        (map, errors)
+    }
+
+    /// The keys of an action as they are written in the config file
+    pub fn keys_value(&self, action: KeyAction) -> toml::Value {
+        toml::Value::Array(self.keys_for(action).iter()
+            .map(|key| toml::Value::String(key.to_string()))
+            .collect())
+    }
+
+    /// Lines for the keys section of the config file
+    pub fn to_lines(&self) -> Vec<String> {
+        KeyAction::ALL.iter()
+            .map(|action| format!("{} = {}", action.name(), self.keys_value(*action)))
+            .collect()
+    }
+}
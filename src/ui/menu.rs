@@ -0,0 +1,174 @@
+use crate::game::tootris::{BlockColor, GameBlock, GameMode};
+use crate::ui::keymap::KeyAction;
+
+/**
+* What picking a menu entry does. Entries with options (board size..) are changed with left/right
+* and their action tells the ui what the option is about.
+*/
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuAction {
+    Play(GameMode),
+    BoardSize,
+    Randomizer,
+    HighScores,
+    Settings,
+    Quit,
+    Resume,
+    Restart,
+    QuitToMenu,
+    Replay,
+    /// Wait for a new key for the action
+    Bind(KeyAction),
+    Das,
+    Arr,
+    SoftDrop,
+    Ghost,
+    Preview,
+    /// Leave the settings
+    Back,
+}
+
+pub struct MenuEntry {
+    pub label: String,
+    pub options: Vec<String>,
+    pub choice: usize,
+    pub action: MenuAction,
+}
+
+/**
+* A vertical list of entries navigated with the arrow keys, drawn as a full screen
+*/
+pub struct Menu {
+    pub title: String,
+    /// Lines shown between the title and the entries
+    pub header: Vec<GameBlock>,
+    pub entries: Vec<MenuEntry>,
+    pub selected: usize,
+    /// Compact menus are drawn inside the well, without title and help
+    pub compact: bool,
+}
+
+impl Menu {
+    pub fn new(title: &str) -> Self {
+        Menu {
+            title: title.to_string(),
+            header: Vec::new(),
+            entries: Vec::new(),
+            selected: 0,
+            compact: false,
+        }
+    }
+
+    pub fn header(mut self, lines: Vec<GameBlock>) -> Self {
+        self.header = lines;
+        self
+    }
+
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+
+    pub fn entry(mut self, label: &str, action: MenuAction) -> Self {
+        self.entries.push(MenuEntry {
+            label: label.to_string(),
+            options: Vec::new(),
+            choice: 0,
+            action,
+        });
+        self
+    }
+
+    pub fn entry_with_options(mut self, label: &str, options: Vec<String>, choice: usize,
+                              action: MenuAction) -> Self {
+        self.entries.push(MenuEntry {
+            label: label.to_string(),
+            options,
+            choice,
+            action,
+        });
+        self
+    }
+
+    pub fn up(&mut self) {
+        if self.selected == 0 {
+            self.selected = self.entries.len() - 1;
+        } else {
+            self.selected -= 1;
+        }
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1) % self.entries.len();
+    }
+
+    /// Cycles the options of the selected entry, returns false if it has none
+    pub fn left(&mut self) -> bool {
+        let entry = &mut self.entries[self.selected];
+        if entry.options.is_empty() {
+            return false;
+        }
+        entry.choice = (entry.choice + entry.options.len() - 1) % entry.options.len();
+        true
+    }
+
+    pub fn right(&mut self) -> bool {
+        let entry = &mut self.entries[self.selected];
+        if entry.options.is_empty() {
+            return false;
+        }
+        entry.choice = (entry.choice + 1) % entry.options.len();
+        true
+    }
+
+    pub fn selected_action(&self) -> MenuAction {
+        self.entries[self.selected].action
+    }
+
+    pub fn has(&self, action: MenuAction) -> bool {
+        self.entries.iter().any(|entry| entry.action == action)
+    }
+
+    /// The current option of the first entry with the given action
+    pub fn choice_of(&self, action: MenuAction) -> usize {
+        self.entries.iter()
+            .find(|entry| entry.action == action)
+            .map(|entry| entry.choice)
+            .unwrap_or(0)
+    }
+
+    pub fn to_lines(&self) -> Vec<GameBlock> {
+        let mut lines = Vec::new();
+        if !self.compact {
+            lines.push(GameBlock::String(self.title.clone(), BlockColor::Magenta));
+            lines.push(GameBlock::String(String::new(), BlockColor::White));
+            if !self.header.is_empty() {
+                lines.extend(self.header.iter().cloned());
+                lines.push(GameBlock::String(String::new(), BlockColor::White));
+            }
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            let text = if self.compact {
+                format!("{} {:<w$}", marker, entry.label, w = self.label_width())
+            } else if entry.options.is_empty() {
+                format!(" {} {}", marker, entry.label)
+            } else {
+                format!(" {} {:<14} < {} >", marker, entry.label, entry.options[entry.choice])
+            };
+            let color = if i == self.selected { BlockColor::Yellow } else { BlockColor::White };
+            lines.push(GameBlock::String(text, color));
+        }
+        if !self.compact {
+            lines.push(GameBlock::String(String::new(), BlockColor::White));
+            lines.push(GameBlock::String(String::from("(arrows to move, Enter to pick)"),
+                                         BlockColor::White));
+        }
+        lines
+    }
+
+    /// Compact entries are padded to the same width so they line up when centered
+    fn label_width(&self) -> usize {
+        self.entries.iter().map(|entry| entry.label.chars().count()).max().unwrap_or(0)
+    }
+}
@@ -1,3 +1,13 @@
 mod settings;
-pub(crate) mod crossterm_ui;
+pub mod crossterm_ui;
 pub mod crossterm_render;
+pub mod terminal_guard;
+pub(crate) mod menu;
+pub(crate) mod auto_shift;
+pub(crate) mod keymap;
+pub mod config;
+pub mod text_ui;
+pub mod text_render;
+pub mod render_loop;
+pub mod headless;
+pub mod snapshot;
@@ -0,0 +1,52 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::game::protocol::{Renderer, WakeupReceiver};
+
+/**
+* Keeps frames at least a frame time apart, for a renderer that draws whenever something
+* comes in. Whatever comes in while it holds off is drawn together in the next frame.
+*/
+pub struct FramePacer {
+    pub frame_time: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(max_fps: usize) -> Self {
+        FramePacer {
+            frame_time: Duration::from_secs(1) / max_fps.max(1) as u32,
+            last_frame: None,
+        }
+    }
+
+    /// How long to hold off before the next frame can be drawn
+    pub fn wait_before_frame(&self, now: Instant) -> Duration {
+        match self.last_frame {
+            Some(last) => (last + self.frame_time).saturating_duration_since(now),
+            None => Duration::ZERO,
+        }
+    }
+
+    pub fn frame_drawn(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+    }
+}
+
+/**
+* Runs the renderer until it quits, sleeping until something is sent to it. Each time it wakes
+* up it draws everything sent so far as one frame, but no more than max_fps frames a second.
+*/
+pub fn run_renderer<R: Renderer>(mut renderer: R, woken: WakeupReceiver, max_fps: usize) {
+    let mut pacer = FramePacer::new(max_fps);
+    loop {
+        thread::sleep(pacer.wait_before_frame(Instant::now()));
+        //the render takes in everything that rang until now
+        woken.clear();
+        pacer.frame_drawn(Instant::now());
+        if !renderer.render() {
+            break;
+        }
+        woken.wait(None);
+    }
+}
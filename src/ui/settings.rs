@@ -8,5 +8,16 @@ pub(crate) static ROT_RIGHT_COMMAND: &str = "R,rr,rori";
 
 pub(crate) static DROP_DOWN_COMMAND: &str = "drop";
 pub(crate) static QUIT_COMMAND: &str = "quit,exit";
+pub(crate) static NEW_GAME_COMMAND: &str = "n,new";
+/// Highest repeat count in front of a text command, as in "3l"
+pub(crate) static TEXT_MAX_REPEAT: usize = 99;
 
-pub (crate) static BLOCK_CLI_CHARS: &str = "██";
\ No newline at end of file
+pub (crate) static BLOCK_CLI_CHARS: &str = "██";
+
+/// Width and height of the level, border included, offered in the start menu
+pub(crate) static BOARD_SIZE_PRESETS: [(usize, usize); 4] = [(8, 18), (10, 24), (12, 24), (16, 28)];
+
+/// Values offered in the settings menu, a different value from the config file is added in
+pub(crate) static DAS_CHOICES: [u64; 11] = [0, 50, 80, 100, 120, 133, 150, 170, 200, 250, 300];
+pub(crate) static ARR_CHOICES: [u64; 8] = [0, 10, 16, 20, 33, 50, 80, 100];
+pub(crate) static SOFT_DROP_CHOICES: [u64; 6] = [0, 2, 5, 10, 20, 40];
@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+
+use crossterm::style::Color;
+
+use crate::game::game_loop_controller::EvilGameMaster;
+use crate::game::protocol::{GameBroadcaster, GameUpdateReceiver, Renderer};
+use crate::game::tootris::{GameConfig, UiCommand};
+use crate::ui::config::DisplayConfig;
+use crate::ui::headless::{Cell, HeadlessRenderer, ScreenGrid};
+use crate::ui::text_ui::{parse_line, TextCommand};
+
+/// Set to anything to write the snapshots instead of comparing against them
+pub static UPDATE_SNAPSHOTS_VAR: &str = "TOOTRIS_UPDATE_SNAPSHOTS";
+static COLOR_LEGEND: &str =
+    "# colors: b blue, m magenta, y yellow, g green, c cyan, w white, k grey, upper case is bold";
+/// Lines of unchanged frame around each difference
+static DIFF_CONTEXT: usize = 2;
+
+/**
+* Plays a script on a game master and keeps the screen the renderer draws after every step.
+* Every line of the script is one step: the commands on it are played as in the text interface
+* (`3l u drop`), then the game advances one frame. An empty line only ticks, lines starting with
+* '#' are skipped. Gravity moves one row every frame and pieces lock, spawn and clear rows
+* without delay, so the frames come out the same every run for a seeded game.
+*/
+pub struct SnapshotRun {
+    pub master: EvilGameMaster,
+    pub renderer: HeadlessRenderer,
+    pub frames: Vec<String>,
+}
+
+impl SnapshotRun {
+    pub fn new(config: &GameConfig, display: DisplayConfig, size: (u16, u16)) -> Self {
+        let mut config = *config;
+        config.tick_base_ms = 0;
+        let (master_sender, render_receiver) = channel();
+        let mut master = EvilGameMaster::new(&config, None, Some(GameBroadcaster::new(master_sender)),
+                                             None, None);
+        master.countdown_ms = 0;
+        master.lock_delay_frames = 0;
+        master.are_frames = 0;
+        master.line_clear_frames = 0;
+        let renderer = HeadlessRenderer::new(Some(GameUpdateReceiver { receiver: render_receiver }), None,
+                                             Some(master.level.clone()), display, size);
+        master.new_game();
+        master.resume_game();
+        SnapshotRun {
+            master,
+            renderer,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Plays one line of the script and keeps the frame it leaves on the screen
+    pub fn step(&mut self, line: &str) -> Result<(), String> {
+        for command in parse_line(line)? {
+            match command {
+                TextCommand::Move(player_move) => {
+                    self.master.process_move(&player_move);
+                }
+                TextCommand::Command(UiCommand::New) => {
+                    self.master.new_game();
+                    self.master.resume_game();
+                }
+                TextCommand::Command(UiCommand::Exit) => self.master.exit(),
+                TextCommand::Command(_) => {}
+            }
+        }
+        self.master.step();
+        self.renderer.render();
+        let title = if line.trim().is_empty() { "tick" } else { line.trim() };
+        self.frames.push(format!("--- frame {}: {} ---\n{}", self.frames.len() + 1, title,
+                                 frame_text(self.renderer.screen())));
+        Ok(())
+    }
+
+    pub fn play(&mut self, script: &str) -> Result<(), String> {
+        for line in script.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            self.step(line).map_err(|e| format!("{}: {}", line.trim(), e))?;
+        }
+        Ok(())
+    }
+
+    /// All frames so far, as they are kept in a snapshot file
+    pub fn text(&self) -> String {
+        let mut text = format!("{}\n", COLOR_LEGEND);
+        for frame in self.frames.iter() {
+            text.push_str(frame);
+        }
+        text
+    }
+}
+
+/**
+* The screen as text, each row followed by the colors of its characters, one letter per
+* character as in the legend of the snapshot files. Blank rows at the bottom are left out.
+*/
+pub fn frame_text(screen: &ScreenGrid) -> String {
+    let rows: Vec<(String, String)> = screen.rows().iter().map(|row| {
+        let used = row.iter().rposition(|cell| *cell != Cell::BLANK).map_or(0, |last| last + 1);
+        let glyphs: String = row[..used].iter().map(|cell| cell.ch).collect();
+        let colors: String = row[..used].iter().map(color_code).collect();
+        (glyphs, colors.trim_end().to_string())
+    }).collect();
+    let shown = rows.iter().rposition(|(glyphs, _)| !glyphs.trim().is_empty()).map_or(0, |last| last + 1);
+    let width = rows[..shown].iter().map(|(glyphs, _)| glyphs.chars().count()).max().unwrap_or(0);
+    let mut text = String::new();
+    for (glyphs, colors) in rows[..shown].iter() {
+        let padding = width - glyphs.chars().count();
+        let line = format!("{}{} | {}", glyphs, " ".repeat(padding), colors);
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+fn color_code(cell: &Cell) -> char {
+    let code = match cell.color {
+        None => return ' ',
+        Some(Color::Blue) => 'b',
+        Some(Color::Magenta) => 'm',
+        Some(Color::Yellow) => 'y',
+        Some(Color::Green) => 'g',
+        Some(Color::Cyan) => 'c',
+        Some(Color::White) => 'w',
+        Some(Color::Grey) => 'k',
+        Some(_) => '?',
+    };
+    if cell.bold { code.to_ascii_uppercase() } else { code }
+}
+
+pub fn snapshot_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.snap", name))
+}
+
+/**
+* Compares the text with the snapshot file, or writes the file when updating. The error holds
+* a diff of the two when they differ.
+*/
+pub fn check_snapshot(path: &Path, actual: &str, update: bool) -> Result<(), String> {
+    if update {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+        }
+        return fs::write(path, actual).map_err(|e| format!("could not write {}: {}", path.display(), e));
+    }
+    let expected = fs::read_to_string(path).map_err(|_| {
+        format!("no snapshot at {}, run the test with {}=1 to write it", path.display(), UPDATE_SNAPSHOTS_VAR)
+    })?;
+    if expected == actual {
+        return Ok(());
+    }
+    Err(format!("{} doesn't match, - is the snapshot and + what was rendered, run the test with {}=1 \
+                 to accept the new frames\n{}", path.display(), UPDATE_SNAPSHOTS_VAR, diff(&expected, actual)))
+}
+
+/// Panics with a diff if the text isn't what the named snapshot has
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let update = std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some();
+    if let Err(e) = check_snapshot(&snapshot_path(name), actual, update) {
+        panic!("{}", e);
+    }
+}
+
+/**
+* The lines that differ between the two texts, - for removed and + for added, with a few
+* unchanged lines around them and the frame headers kept so it's clear where a change is.
+*/
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    //longest common subsequence, from the back so the walk below can go forward
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut lines: Vec<(char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = (0..lines.len()).filter(|k| lines[*k].0 != ' ').collect();
+    let mut text = String::new();
+    let mut shown_until = 0;
+    for (k, (mark, line)) in lines.iter().enumerate() {
+        let near = changed.iter().any(|c| k + DIFF_CONTEXT >= *c && k <= c + DIFF_CONTEXT);
+        let header = line.starts_with("--- frame") && changed.iter().any(|c| *c > k
+            && !lines[k + 1..*c].iter().any(|(_, other)| other.starts_with("--- frame")));
+        if !near && !header {
+            continue;
+        }
+        if k > shown_until && !text.is_empty() {
+            text.push_str("  ...\n");
+        }
+        text.push_str(&format!("{} {}\n", mark, line));
+        shown_until = k + 1;
+    }
+    text
+}
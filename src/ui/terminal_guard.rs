@@ -0,0 +1,91 @@
+use std::io::{stdout, Result, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crossterm::{cursor, execute, terminal};
+use crossterm::event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{Attribute, SetAttribute};
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+
+/// The flags were pushed and have to be popped again, restore_terminal can run from any thread
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
+
+/**
+* Owns the terminal for as long as the game runs. Creating the guard switches to the alternate
+* screen, enables raw mode, hides the cursor and asks for key release events where the terminal
+* supports them. The terminal is put back the way we found it when the guard is dropped, when any
+* thread panics and when the process receives SIGINT/SIGTERM.
+*/
+pub struct TerminalGuard {
+    enhanced: bool,
+}
+
+impl TerminalGuard {
+    pub fn new() -> Result<Self> {
+        install_panic_hook();
+        install_signal_handler();
+
+        terminal::enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen, cursor::DisableBlinking, cursor::Hide)?;
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))
+                .is_ok();
+        KEYBOARD_ENHANCED.store(enhanced, Ordering::SeqCst);
+        Ok(TerminalGuard { enhanced })
+    }
+
+    /// true if keys come with release events, Windows consoles always send them
+    pub fn reports_releases(&self) -> bool {
+        self.enhanced || cfg!(windows)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Undoes everything the guard did. Safe to call more than once.
+pub fn restore_terminal() {
+    let mut out = stdout();
+    if KEYBOARD_ENHANCED.swap(false, Ordering::SeqCst) {
+        let _ = execute!(out, PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(out, SetAttribute(Attribute::Reset), cursor::Show, cursor::EnableBlinking,
+                     LeaveAlternateScreen);
+    let _ = out.flush();
+    let _ = terminal::disable_raw_mode();
+}
+
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        //restore first, otherwise the message ends up on the alternate screen and vanishes
+        restore_terminal();
+        default_hook(info);
+    }));
+}
+
+#[cfg(unix)]
+fn install_signal_handler() {
+    use signal_hook::iterator::Signals;
+    use signal_hook::{SIGINT, SIGTERM};
+
+    let signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(_) => {
+            eprintln!("Could not register signal handler");
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            restore_terminal();
+            std::process::exit(128 + signal);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn install_signal_handler() {}
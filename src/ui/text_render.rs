@@ -0,0 +1,132 @@
+use std::io::{Stdout, Write};
+
+use crate::game::tootris::{GameBlock, GameMatrix, GameState, UiCommand};
+use crate::game::protocol::{apply_changes, GameEvent, GameUpdateReceiver, Renderer, UiEvent};
+
+/**
+* Prints the level as plain text whenever it changes, for terminals and pipes that can't take
+* escape codes. Goes with the TextUI.
+*/
+pub struct TextRenderer {
+    pub from_master: Option<GameUpdateReceiver<GameEvent>>,
+    pub from_ui: Option<GameUpdateReceiver<UiEvent>>,
+    pub out: Option<Stdout>,
+    pub level: Option<GameMatrix>,
+    pub state: Option<GameState>,
+    pub score: usize,
+    /// The level and state that were printed last, to skip printing the same board twice
+    printed: Option<(GameMatrix, Option<GameState>)>,
+}
+
+impl TextRenderer {
+    pub fn new(from_master: Option<GameUpdateReceiver<GameEvent>>,
+               from_ui: Option<GameUpdateReceiver<UiEvent>>,
+               out: Option<Stdout>) -> Self {
+        TextRenderer {
+            from_master,
+            from_ui,
+            out,
+            level: None,
+            state: None,
+            score: 0,
+            printed: None,
+        }
+    }
+
+    /// Takes in everything the master sent since last time, returns false once it is time to quit
+    fn master_updates(&mut self) -> bool {
+        if self.from_master.is_none() {
+            return true;
+        }
+        let mut exiting = false;
+        for event in self.from_master.as_ref().unwrap().pending() {
+            match event {
+                GameEvent::Board(level) => self.level = Some(level),
+                GameEvent::BoardUpdate(changes) => {
+                    if let Some(level) = self.level.as_mut() {
+                        apply_changes(level, &changes);
+                    }
+                }
+                GameEvent::Score(score) => self.score = score,
+                GameEvent::StateChanged(GameState::Exit) => exiting = true,
+                GameEvent::StateChanged(state) => self.state = Some(state),
+                _ => {}
+            }
+        }
+        if exiting && self.state != Some(GameState::Exit) {
+            //whatever came in before the exit still gets printed
+            self.print_board();
+            self.state = Some(GameState::Exit);
+        }
+        self.state != Some(GameState::Exit)
+    }
+
+    fn ui_updates(&mut self) -> bool {
+        if self.from_ui.is_none() {
+            return true;
+        }
+        !self.from_ui.as_ref().unwrap().pending().any(|event| event == UiEvent::Command(UiCommand::Exit))
+    }
+
+    fn print_board(&mut self) {
+        let level = match self.level.as_ref() {
+            Some(level) => level,
+            None => return,
+        };
+        let already_printed = self.printed.as_ref()
+            .is_some_and(|(printed, state)| printed == level && *state == self.state);
+        if already_printed || self.out.is_none() {
+            return;
+        }
+        let text = board_text(level, self.state, self.score);
+        self.printed = Some((level.clone(), self.state));
+        let out = self.out.as_mut().unwrap();
+        if out.write_all(text.as_bytes()).and_then(|_| out.flush()).is_err() {
+            //the other end of the pipe is gone, nobody to play for
+            self.state = Some(GameState::Exit);
+        }
+    }
+}
+
+/**
+* The level as lines of text under a status line, '#' for the border, 'O' for blocks,
+* '+' where the piece would land and '.' for empty cells.
+*/
+pub fn board_text(level: &[Vec<GameBlock>], state: Option<GameState>, score: usize) -> String {
+    let mut text = match state {
+        Some(GameState::End) => format!("game over, score: {}\n", score),
+        Some(GameState::Playing) | None => format!("score: {}\n", score),
+        Some(state) => format!("score: {} ({})\n", score, state),
+    };
+    for row in level {
+        for block in row {
+            text.push(match block {
+                GameBlock::Filled(_) => 'O',
+                GameBlock::Indestructible => '#',
+                GameBlock::Ghost(_) => '+',
+                _ => '.',
+            });
+        }
+        text.push('\n');
+    }
+    text.push('\n');
+    text
+}
+
+impl Renderer for TextRenderer {
+    fn render(&mut self) -> bool {
+        if !self.master_updates() || !self.ui_updates() {
+            return false;
+        }
+        self.print_board();
+        self.state != Some(GameState::Exit)
+    }
+
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>) {
+        self.from_master = Some(receiver);
+    }
+
+    fn give_ui_receiver(&mut self, receiver: GameUpdateReceiver<UiEvent>) {
+        self.from_ui = Some(receiver);
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::BufRead;
+
+use crate::game::tootris::{PlayerMove, UiCommand};
+use crate::game::protocol::{GameBroadcaster, GameEvent, GameUpdateReceiver, UIHandler, UiEvent};
+use crate::ui::settings::*;
+
+/**
+* One parsed word of a text command line
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub enum TextCommand {
+    Move(PlayerMove),
+    Command(UiCommand),
+}
+
+/// The command for a single alias, without a repeat count
+fn command_for(alias: &str) -> Option<TextCommand> {
+    let moves = [
+        (MOVE_LEFT_COMMAND, PlayerMove::StepLeft),
+        (MOVE_RIGHT_COMMAND, PlayerMove::StepRight),
+        (MOVE_DOWN_COMMAND, PlayerMove::StepDown),
+        (ROT_UP_COMMAND, PlayerMove::OrientUp),
+        (ROT_DOWN_COMMAND, PlayerMove::OrientDown),
+        (ROT_LEFT_COMMAND, PlayerMove::OrientLeft),
+        (ROT_RIGHT_COMMAND, PlayerMove::OrientRight),
+        (DROP_DOWN_COMMAND, PlayerMove::HardDrop),
+    ];
+    if let Some((_, mov)) = moves.iter().find(|(aliases, _)| aliases.split(',').any(|a| a == alias)) {
+        return Some(TextCommand::Move(*mov));
+    }
+    if QUIT_COMMAND.split(',').any(|a| a == alias) {
+        return Some(TextCommand::Command(UiCommand::Exit));
+    }
+    if NEW_GAME_COMMAND.split(',').any(|a| a == alias) {
+        return Some(TextCommand::Command(UiCommand::New));
+    }
+    None
+}
+
+/**
+* Parses a line of whitespace separated commands, each with an optional repeat count in front,
+* e.g. "3l u drop". Aliases are case sensitive, 'd' steps down while 'D' turns the piece upside
+* down. Nothing on the line is used if any of it is wrong.
+*/
+pub fn parse_line(line: &str) -> Result<Vec<TextCommand>, String> {
+    let line = line.trim();
+    let mut commands = Vec::new();
+    if line.starts_with('#') {
+        return Ok(commands);
+    }
+    for word in line.split_whitespace() {
+        let digits = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+        let (count, alias) = word.split_at(digits);
+        let count = if count.is_empty() {
+            1
+        } else {
+            match count.parse::<usize>() {
+                Ok(count) if count > 0 && count <= TEXT_MAX_REPEAT => count,
+                _ => return Err(format!("bad repeat count in '{}', use 1 to {}", word, TEXT_MAX_REPEAT)),
+            }
+        };
+        let command = command_for(alias).ok_or_else(|| format!("unknown command '{}'", word))?;
+        for _ in 0..count {
+            commands.push(command.clone());
+        }
+    }
+    Ok(commands)
+}
+
+/**
+* Plays the game from lines of text, so it can be driven through a pipe or a script.
+* Starts a game right away, EOF quits.
+*/
+pub struct TextUI {
+    pub to_master: Option<GameBroadcaster<UiEvent>>,
+    pub from_master: Option<GameUpdateReceiver<GameEvent>>,
+    input: Box<dyn BufRead + Send>,
+}
+
+impl TextUI {
+    pub fn new(to_master: Option<GameBroadcaster<UiEvent>>,
+               from_master: Option<GameUpdateReceiver<GameEvent>>,
+               input: Box<dyn BufRead + Send>) -> Self {
+        let mut ui = TextUI {
+            to_master,
+            from_master,
+            input,
+        };
+        ui.send_to_master(UiEvent::Command(UiCommand::New));
+        ui
+    }
+
+    fn send_to_master(&mut self, event: UiEvent) {
+        if self.to_master.is_none() {
+            return;
+        }
+        if self.to_master.as_mut().unwrap().send(event).is_err() {
+            eprintln!("failed to send controller command");
+        }
+    }
+
+    /// The master passes the exit on to the renderer, after the moves that came before it
+    fn quit(&mut self) {
+        self.send_to_master(UiEvent::Command(UiCommand::Exit));
+    }
+
+    /// Nothing is shown from the master's updates, the renderer prints the board and the score
+    fn drain_master_updates(&mut self) {
+        if let Some(from_master) = self.from_master.as_ref() {
+            from_master.pending().for_each(drop);
+        }
+    }
+}
+
+impl UIHandler for TextUI {
+    fn handle_ui(&mut self) -> bool {
+        self.drain_master_updates();
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                self.quit();
+                return false;
+            }
+            Ok(_) => {}
+        }
+        let commands = match parse_line(&line) {
+            Ok(commands) => commands,
+            Err(e) => {
+                eprintln!("{}", e);
+                return true;
+            }
+        };
+        for command in commands {
+            match command {
+                TextCommand::Move(mov) => self.send_to_master(UiEvent::Move(mov)),
+                TextCommand::Command(UiCommand::Exit) => {
+                    self.quit();
+                    return false;
+                }
+                TextCommand::Command(command) => self.send_to_master(UiEvent::Command(command)),
+            }
+        }
+        true
+    }
+
+    fn give_master_receiver(&mut self, receiver: GameUpdateReceiver<GameEvent>) {
+        self.from_master = Some(receiver);
+    }
+
+    fn give_master_broadcaster(&mut self, broadcaster: GameBroadcaster<UiEvent>) {
+        self.to_master = Some(broadcaster);
+    }
+
+    /// There is nothing to draw besides the board, which comes from the master
+    fn give_render_broadcaster(&mut self, _broadcaster: GameBroadcaster<UiEvent>) {}
+}